@@ -0,0 +1,82 @@
+pub const SAMPLE_RATE: u32 = 44100;
+const CPU_CLOCK: u32 = 1_789_773; //NTSC
+const MAX_SAMPLES: usize = SAMPLE_RATE as usize/10; //about 6 frames, samples beyond that are dropped until the buffer is drained
+
+//placeholder APU: takes the register writes and produces silence at the output sample rate
+#[derive(Clone)]
+pub struct APU{
+    samples: Vec<f32>,
+    sampleClock: u32, //CPU cycles times SAMPLE_RATE, a sample is due every CPU_CLOCK
+}
+
+impl APU{
+    pub fn new() -> Self{
+        APU{
+            samples: Vec::with_capacity(MAX_SAMPLES),
+            sampleClock: 0,
+        }
+    }
+    
+    pub fn reset(&mut self){
+        self.sampleClock = 0;
+    }
+    
+    //$4015
+    pub fn read(&mut self, _adr: u16) -> u8{
+        0
+    }
+    
+    //$4000-$4013, $4015 and $4017
+    pub fn write(&mut self, _adr: u16, _data: u8){
+    }
+    
+    //called once per CPU cycle
+    pub fn tick(&mut self){
+        self.sampleClock += SAMPLE_RATE;
+        if self.sampleClock >= CPU_CLOCK{
+            self.sampleClock -= CPU_CLOCK;
+            //never grows past the preallocated capacity
+            if self.samples.len() < MAX_SAMPLES{
+                self.samples.push(0.0);
+            }
+        }
+    }
+    
+    pub fn samples(&self) -> &[f32]{
+        &self.samples
+    }
+    
+    pub fn clearSamples(&mut self){
+        self.samples.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn sampleRate(){
+        //one frame
+        let mut apu = APU::new();
+        for _ in 0..29781{
+            apu.tick();
+        }
+        assert_eq!(apu.samples().len(), 29781*SAMPLE_RATE as usize/CPU_CLOCK as usize);
+    }
+
+    #[test]
+    fn undrainedSamplesDontGrow(){
+        let mut apu = APU::new();
+        let capacity = apu.samples.capacity();
+        for _ in 0..CPU_CLOCK*2{
+            apu.tick();
+        }
+        assert_eq!(apu.samples().len(), MAX_SAMPLES);
+        assert_eq!(apu.samples.capacity(), capacity);
+        
+        apu.clearSamples();
+        apu.tick();
+        assert!(apu.samples().len() <= 1);
+    }
+}
@@ -0,0 +1,36 @@
+use crate::CPU::CpuBus;
+use crate::Cartridge_NES::Cartridge;
+
+//every address goes to the cartridge, for raw 64K test binaries
+#[derive(Clone)]
+pub struct FlatBus{
+    pub cart: Cartridge,
+}
+
+impl FlatBus{
+    pub fn new(cart: Cartridge) -> Self{
+        FlatBus{ cart }
+    }
+}
+
+impl CpuBus for FlatBus{
+    fn read(&mut self, adr: u16) -> u8{
+        self.cart.cpuRead(adr)
+    }
+    
+    fn write(&mut self, adr: u16, data: u8){
+        self.cart.cpuWrite(adr, data);
+    }
+    
+    fn peek(&mut self, adr: u16) -> u8{
+        self.cart.cpuRead(adr)
+    }
+    
+    fn tick(&mut self){
+        self.cart.cpuCycle();
+    }
+    
+    fn irq(&self) -> bool{
+        self.cart.irq()
+    }
+}
@@ -1,52 +1,140 @@
 const MEM_SIZE:usize = 0x800;
-use crate::Cartridge;
-use crate::Mapper;
-use crate::PPU;
+const OAM_DMA_CYCLES: u16 = 513; //halt cycle then 256 get/put pairs, plus one to align on a get cycle
+use crate::CPU::CpuBus;
+use crate::Cartridge_NES::Cartridge;
+use crate::PPU_NES::PPU;
+use crate::APU_NES::APU;
 
-pub struct Bus<'a>{
+//the CPU memory map of the NES, owns everything hanging off the CPU bus
+#[derive(Clone)]
+pub struct Bus{
     memory: Vec<u8>,
-    cart: &'a mut Cartridge,
-    ppu: &'a mut PPU,
+    pub cart: Cartridge,
+    pub ppu: PPU,
+    pub apu: APU,
+    
+    cycle: u64,
+    dmaPage: Option<u8>, //set by $4014, the copy starts on the next cycle
+    dmaStep: u16,
+    dmaData: u8,
 }
 
-impl<'a> Bus<'a>{
-    pub fn read(&self, adr: usize)->u8{
-        return self.cart.read(adr);
+impl Bus{
+    pub fn new(cart: Cartridge)->Self{
+        let mem = vec![0; MEM_SIZE];
+        Bus{
+            memory:mem,
+            cart,
+            ppu: PPU::new(),
+            apu: APU::new(),
+            cycle: 0,
+            dmaPage: None,
+            dmaStep: 0,
+            dmaData: 0,
+        }
+    }
+    
+    pub fn reset(&mut self){
+        self.ppu.reset();
+        self.apu.reset();
+    }
+    
+    //everything but the cartridge (and the renderer choice) starts over
+    pub fn powerCycle(&mut self){
+        self.memory.fill(0);
+        let renderer = self.ppu.renderer();
+        self.ppu = PPU::new();
+        self.ppu.setRenderer(renderer);
+        self.apu = APU::new();
+        self.cycle = 0;
+        self.dmaPage = None;
+        self.dmaStep = 0;
+    }
+    
+    //CPU cycles since power on, DMA reads happen on even ones
+    pub fn cycle(&self) -> u64{
+        self.cycle
+    }
+    
+    fn oamDMA(&mut self, page: u8){
+        match self.dmaStep{
+            0 => (), //the CPU halts
+            _ if self.dmaStep % 2 == 1 => {
+                if self.cycle % 2 == 1{
+                    return; //alignment cycle, the transfer waits for a get cycle
+                }
+                let adr = ((page as u16) << 8) | ((self.dmaStep - 1)/2);
+                self.dmaData = self.read(adr);
+            }
+            _ => self.write(0x2004, self.dmaData),
+        }
+        self.dmaStep += 1;
+        if self.dmaStep == OAM_DMA_CYCLES{
+            self.dmaPage = None;
+            self.dmaStep = 0;
+        }
+    }
+}
+
+impl CpuBus for Bus{
+    fn read(&mut self, adr: u16)->u8{
+        let adr = adr as usize;
         match adr as u16{
             0x0000..=0x07FF => self.memory[adr-0x0000], //RAM
             0x0800..=0x0FFF => self.memory[adr-0x0800], //Mirrors
             0x1000..=0x17FF => self.memory[adr-0x1000],
             0x1800..=0x1FFF => self.memory[adr-0x1800],
-            0x2000..=0x3FFF => self.ppu.read(adr), //PPU registers and mirrors
-            0x4000..=0x401F => 0, //APU and IO stuff
-            0x4020..=0xFFFF => self.cart.read(adr),//-0x4020), //Cartridge space
+            0x2000..=0x3FFF => self.ppu.read(0x2000 + (adr as u16 & 7), &mut self.cart), //PPU registers and mirrors
+            0x4015 => self.apu.read(adr as u16),
+            0x4000..=0x401F => 0, //IO stuff
+            0x4020..=0xFFFF => self.cart.cpuRead(adr as u16),//-0x4020), //Cartridge space
         }
     }
     
-    pub fn write(&mut self, adr:usize, data: u8){
-        self.cart.write(adr, data);
-        return;
-        //self.memory[adr] = data;
+    fn write(&mut self, adr: u16, data: u8){
+        let adr = adr as usize;
         match adr as u16{
             0x0000..=0x07FF => self.memory[adr-0x0000] = data,        //RAM
             0x0800..=0x0FFF => self.memory[adr-0x0800] = data, //Mirrors
             0x1000..=0x17FF => self.memory[adr-0x1000] = data,
             0x1800..=0x1FFF => self.memory[adr-0x1800] = data,
-            0x2000..=0x3FFF => self.ppu.write(adr, data), //PPU registers and mirrors
-            0x4000..=0x401F => (), //APU and IO stuff
-            0x4020..=0xFFFF => self.cart.write(adr, data), //-0x4020, data), //Cartridge space
+            0x2000..=0x3FFF => self.ppu.write(0x2000 + (adr as u16 & 7), data, &mut self.cart), //PPU registers and mirrors
+            0x4000..=0x4013 | 0x4015 | 0x4017 => self.apu.write(adr as u16, data),
+            0x4014 => self.dmaPage = Some(data), //OAM DMA
+            0x4016..=0x401F => (), //IO stuff
+            0x4020..=0xFFFF => self.cart.cpuWrite(adr as u16, data), //-0x4020, data), //Cartridge space
         }
     }
     
-    pub fn new(cart: &'a mut Cartridge, ppu: &'a mut PPU)->Self{
-        //let mem = vec![0x69, 8, 0x69, 15, 0x65, 3];
-        let mem = vec![0; MEM_SIZE];
-        //mem[0xFFFC] = 0x00;
-        //mem[0xFFFD] = 0x0C;
-        Bus{
-            memory:mem,
-            cart: cart,
-            ppu: ppu
+    //the registers read as 0
+    fn peek(&mut self, adr: u16) -> u8{
+        match adr{
+            0x0000..=0x1FFF => self.memory[(adr & 0x7FF) as usize],
+            0x2000..=0x401F => 0,
+            0x4020..=0xFFFF => self.cart.cpuRead(adr),
+        }
+    }
+    
+    fn tick(&mut self){
+        self.cycle += 1;
+        self.cart.cpuCycle();
+    }
+    
+    fn dmaActive(&self) -> bool{
+        self.dmaPage.is_some()
+    }
+    
+    fn dmaCycle(&mut self){
+        if let Some(page) = self.dmaPage{
+            self.oamDMA(page);
         }
     }
-}
\ No newline at end of file
+    
+    fn irq(&self) -> bool{
+        self.cart.irq()
+    }
+    
+    fn nmi(&self) -> bool{
+        self.ppu.nmi()
+    }
+}
@@ -1,38 +1,68 @@
 #![allow(arithmetic_overflow)]
 
-use std::{fmt, collections::VecDeque};
-use crate::Bus_NES::*;
+use std::fmt;
+use crate::Opcodes::{*, Mnemonic::*};
 
-fn to16(h:u8,l:u8)->usize{
-    ((h as usize)<<8)+l as usize
+fn to16(h:u8,l:u8)->u16{
+    ((h as u16)<<8)|l as u16
 }
+
+//what the CPU sees of the machine, every read and write is one bus cycle
+pub trait CpuBus{
+    fn read(&mut self, adr: u16) -> u8;
+    fn write(&mut self, adr: u16, data: u8);
+    
+    //called at the start of every CPU cycle, before its access
+    fn tick(&mut self){
+    }
+    
+    //level of the IRQ line, wired-OR of every source on the bus
+    fn irq(&self) -> bool{
+        false
+    }
+    
+    //level of the NMI line, the CPU looks for the edge
+    fn nmi(&self) -> bool{
+        false
+    }
+    
+    //true while a DMA unit owns the bus, the CPU stays halted and dmaCycle runs in its place
+    fn dmaActive(&self) -> bool{
+        false
+    }
+    
+    fn dmaCycle(&mut self){
+    }
+    
+    //read without side effects, for the tracer
+    fn peek(&mut self, adr: u16) -> u8;
+}
+
+#[derive(Clone)]
 struct StatusRegister{
     N: u8,//bit 7
     V: u8,
-    //unused
-    B: u8,//weird wtf
+    //bits 5 and 4 aren't stored, they only exist on the stack
     D: u8,
     I: u8,
     Z: u8,
     C: u8 //bit 0
 }
 
-//TODO: handle weird break flag logic here rather than in the instructions 
 impl StatusRegister{
     fn new() -> StatusRegister{
-        StatusRegister { N: 0, V: 0, B: 0, D: 0, I: 0, Z: 0, C: 0 }
+        StatusRegister { N: 0, V: 0, D: 0, I: 0, Z: 0, C: 0 }
     }
     
-    fn asU8(&self) -> u8{
-        //b flag is not sent here
-        (self.N<<7)|(self.V<<6)|(1u8<<5)|(1u8<<4)|(self.D<<3)|(self.I<<2)|(self.Z<<1)|(self.C<<0)
+    //value pushed on the stack: bit 5 is always set, the B flag (bit 4) is set by BRK and PHP only
+    fn asU8(&self, b: bool) -> u8{
+        (self.N<<7)|(self.V<<6)|(1u8<<5)|((b as u8)<<4)|(self.D<<3)|(self.I<<2)|(self.Z<<1)|(self.C<<0)
     }
     
     fn fromU8(&mut self, data: u8){
         self.N = (data>>7)&1;
         self.V = (data>>6)&1;
-        //unused bit
-        //self.B //weird stuff i don't know
+        //bits 5 and 4 are ignored
         self.D = (data>>3)&1;
         self.I = (data>>2)&1;
         self.Z = (data>>1)&1;
@@ -44,25 +74,57 @@ impl fmt::Display for StatusRegister{
     fn fmt(&self, f:&mut fmt::Formatter<'_>)->fmt::Result{
         let N = match self.N{1=>"N",_=>"-"};
         let V = match self.V{1=>"V",_=>"-"};
-        let B = match self.B{1=>"B",_=>"-"};
         let D = match self.D{1=>"D",_=>"-"};
         let I = match self.I{1=>"I",_=>"-"};
         let Z = match self.Z{1=>"Z",_=>"-"};
         let C = match self.C{1=>"C",_=>"-"};
-        write!(f,"{}{}-{}{}{}{}{}", N,V,B,D,I,Z,C)
+        write!(f,"{}{}--{}{}{}{}", N,V,D,I,Z,C)
     }
 }
-#[derive(PartialEq)]
+#[derive(Clone, Copy, PartialEq)]
 pub enum Interupt{
     RES,
     NMI,
     IRQ,
     BRK
 }
-pub struct CPU6502<'a>{
-    pc: usize, //todo: make this u16
-    oldPC: usize,
-    bus: &'a mut Bus<'a>,
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CpuVariant{
+    NMOS6502,  //original MOS 6502 with decimal mode
+    Ricoh2A03, //NES CPU: the D flag exists but ADC/SBC ignore it
+}
+
+//what tick does with the unstable opcodes and the JAM opcodes
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum UndefinedOpcodePolicy{
+    Halt, //lock the CPU and report CpuError::UndefinedOpcode until reset
+    Nop,  //skip it like a NOP of the same length
+    Trap, //report CpuError::Trap without executing it, the debugger decides what to do next
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum CpuError{
+    UndefinedOpcode{pc: u16, bytes: [u8; 3]},
+    Trap{pc: u16, bytes: [u8; 3]},
+}
+
+impl fmt::Display for CpuError{
+    fn fmt(&self, f:&mut fmt::Formatter<'_>)->fmt::Result{
+        let (name, pc, bytes) = match self{
+            CpuError::UndefinedOpcode{pc, bytes} => ("CPU halted on undefined opcode", pc, bytes),
+            CpuError::Trap{pc, bytes} => ("Trapped undefined opcode", pc, bytes),
+        };
+        write!(f, "{name} at {pc:#06x}: {:02X} {:02X} {:02X} ({})", bytes[0], bytes[1], bytes[2], disassemble(*pc, *bytes))
+    }
+}
+
+impl std::error::Error for CpuError{}
+
+#[derive(Clone)]
+pub struct CPU6502<B: CpuBus>{
+    pc: u16,
+    oldPC: u16,
+    bus: B,
     buffer:u8,
     acc: u8,
     x: u8,
@@ -70,25 +132,39 @@ pub struct CPU6502<'a>{
     status: StatusRegister,
     sp : u8,
     
-    cycles: usize,
-    opcode: String,
-    adrMode: String,
+    ir: u8,     //opcode of the current instruction
+    step: u8,   //cycle of the current instruction, 0 is the opcode fetch
+    adr: u16,   //effective address being built by the addressing mode
+    pointer: u8, //zero page pointer of the indirect modes
+    pageCrossed: bool,
+    interupt: Option<Interupt>, //interupt sequence in progress
+    resetPending: bool,
+    nmiLine: bool,      //external NMI input, true when asserted (low on the real pin)
+    nmiPrevious: bool,
+    nmiPending: bool,   //set by the edge detector, cleared when the NMI is serviced
+    irqLine: bool,      //external IRQ input, wired-OR with the cartridge IRQ
+    polled: bool,       //interupt poll done at the end of the last cycle
+    polledBefore: bool, //and at the end of the cycle before it, which is the one that counts
+    pollSkip: bool,
     takeBranch: bool,
-    interupts: VecDeque<Interupt>
+    variant: CpuVariant,
+    undefinedPolicy: UndefinedOpcodePolicy,
+    skipTrap: bool,
+    fault: Option<CpuError>, //set by the Halt policy, the CPU stays locked until the next reset
 }
 
-impl fmt::Display for CPU6502<'_>{
+impl<B: CpuBus> fmt::Display for CPU6502<B>{
     fn fmt(&self, f:&mut fmt::Formatter<'_>)->fmt::Result{
-        write!(f,"PC  ,ACC, X , Y, NV-BDIZC\n").unwrap();
-        write!(f,"{:#x} {} {} {} {} {} {}", self.pc, self.opcode, self.adrMode, self.acc, self.x, self.y, self.status)
+        writeln!(f,"PC  ,ACC, X , Y, NV-BDIZC").unwrap();
+        write!(f,"{:#x} {} {} {} {} {}", self.pc, OPCODES[self.ir as usize].mnemonic, self.acc, self.x, self.y, self.status)
     }
 }
 
-impl<'a> CPU6502<'a>{
+impl<B: CpuBus> CPU6502<B>{
     //type Instruction = fn (&mut Self)->();
-    pub fn new(bus: &'a mut Bus<'a>)->Self{
+    pub fn new(bus: B)->Self{
         CPU6502 { 
-            bus: bus,
+            bus,
             pc: 0,
             oldPC: 0,
             buffer:0,
@@ -96,241 +172,301 @@ impl<'a> CPU6502<'a>{
             x:0,
             y:0,
             status: StatusRegister::new(),
-            sp: 0xFF,
-            cycles:0,
-            opcode: "".to_owned(),
-            adrMode: "".to_owned(),
+            sp: 0, //RES takes it to $FD
+            ir: 0,
+            step: 0,
+            adr: 0,
+            pointer: 0,
+            pageCrossed: false,
+            interupt: None,
+            resetPending: true,
+            nmiLine: false,
+            nmiPrevious: false,
+            nmiPending: false,
+            irqLine: false,
+            polled: false,
+            polledBefore: false,
+            pollSkip: false,
             takeBranch: false,
-            interupts: vec![Interupt::RES].into()
+            variant: CpuVariant::NMOS6502,
+            undefinedPolicy: UndefinedOpcodePolicy::Halt,
+            skipTrap: false,
+            fault: None,
         }
     }
     
-    pub fn debugMode(&self){
-        if self.cycles == 0{
-            //println!("{self}\n");
-        }
-        if self.pc == self.oldPC {
-            println!("LOOPING !!!");
+    //back to the power-up state, the bus and the configuration are kept
+    pub fn powerCycle(&mut self){
+        self.pc = 0;
+        self.oldPC = 0;
+        self.buffer = 0;
+        self.acc = 0;
+        self.x = 0;
+        self.y = 0;
+        self.status = StatusRegister::new();
+        self.sp = 0;
+        self.ir = 0;
+        self.step = 0;
+        self.adr = 0;
+        self.pointer = 0;
+        self.pageCrossed = false;
+        self.interupt = None;
+        self.resetPending = true;
+        self.nmiLine = false;
+        self.nmiPrevious = false;
+        self.nmiPending = false;
+        self.irqLine = false;
+        self.polled = false;
+        self.polledBefore = false;
+        self.pollSkip = false;
+        self.takeBranch = false;
+        self.skipTrap = false;
+        self.fault = None;
+    }
+    
+    pub fn bus(&self) -> &B{
+        &self.bus
+    }
+    
+    pub fn busMut(&mut self) -> &mut B{
+        &mut self.bus
+    }
+    
+    pub fn setVariant(&mut self, variant: CpuVariant){
+        self.variant = variant;
+    }
+    
+    #[allow(dead_code)]
+    pub fn isHalted(&self) -> bool{
+        self.fault.is_some()
+    }
+    
+    pub fn setUndefinedOpcodePolicy(&mut self, policy: UndefinedOpcodePolicy){
+        self.undefinedPolicy = policy;
+    }
+    
+    //with the Trap policy, executes the trapped opcode as a NOP on the next tick instead of trapping again
+    pub fn skipTrappedOpcode(&mut self){
+        self.skipTrap = true;
+    }
+    
+    fn decimalMode(&self) -> bool{
+        self.status.D == 1 && self.variant == CpuVariant::NMOS6502
+    }
+    
+    pub fn atInstructionBoundary(&self) -> bool{
+        self.step == 0 && !self.resetPending && !self.bus.dmaActive()
+    }
+    
+    //the next instruction and the registers, in the layout of the nestest log
+    pub fn trace(&mut self) -> String{
+        let bytes = [self.bus.peek(self.pc), self.bus.peek(self.pc.wrapping_add(1)), self.bus.peek(self.pc.wrapping_add(2))];
+        let size = OPCODES[bytes[0] as usize].bytes as usize;
+        let hex: Vec<String> = bytes[..size].iter().map(|byte| format!("{byte:02X}")).collect();
+        format!("{:04X}  {:<8}  {:<30}  A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}",
+            self.pc, hex.join(" "), disassemble(self.pc, bytes), self.acc, self.x, self.y, self.status.asU8(false), self.sp)
+    }
+    
+    pub fn pc(&self) -> u16{
+        self.pc
+    }
+    
+    //moves execution somewhere else, between instructions
+    pub fn setPC(&mut self, pc: u16){
+        self.pc = pc;
+    }
+    
+    //the last instruction jumped to itself, test programs stop like that to report their result
+    pub fn inSelfLoop(&self) -> bool{
+        self.atInstructionBoundary() && self.pc == self.oldPC
+    }
+    
+    
+    //runs one CPU cycle, every cycle does the bus access the real CPU does on it (dummy reads and writes included)
+    pub fn tick(&mut self) -> Result<(), CpuError>{
+        self.bus.tick();
+        if self.bus.dmaActive(){
+            self.bus.dmaCycle();
+            return Ok(());
         }
+    
+        let result = if self.step == 0{
+            self.fetch()
+        } else {
+            match self.interupt{
+                Some(_) => self.Interupt(),
+                None => self.execute(),
+            }
+            if self.step != 0{
+                self.step+=1;
+            }
+            Ok(())
+        };
+    
+        self.pollInterupts();
+        result
     }
     
-    pub fn tick(&mut self){
-        if self.cycles != 0{
-            self.cycles-=1;
+    //the NMI edge detector and the IRQ level detector sample the lines at the end of every cycle,
+    //an instruction looks at the result of its second to last cycle to decide if an interupt comes next
+    fn pollInterupts(&mut self){
+        let nmiLine = self.nmiLine || self.bus.nmi();
+        if nmiLine && !self.nmiPrevious{
+            self.nmiPending = true;
+        }
+        self.nmiPrevious = nmiLine;
+    
+        if self.pollSkip{
+            self.pollSkip = false;
             return;
         }
-        
-        if let Some(interupt) = self.interupts.pop_front(){
-            let mut executeInterupt = true;
-            let adr:usize = match interupt{
-                Interupt::NMI => 0xFFFA,
-                Interupt::RES => 0xFFFC,
-                Interupt::BRK => 0xFFFA,
-                Interupt::IRQ => {
-                    if self.status.I == 1{
-                        executeInterupt = false;
-                    }
-                    0xFFFE
-                }
-            };
-            if executeInterupt{
-                println!("EXECUTING INTERUPT !!");
-                self.cycles = 7;
-                
-                let adrLow = self.bus.read(adr);
-                let adrHigh = self.bus.read(adr+1);
-                
-                self.pushPC();
-                self.push(self.status.asU8());
-                
-                self.status.I = 1;
-                self.pc = to16(adrHigh, adrLow);
-                return;
+        self.polledBefore = self.polled;
+        self.polled = self.nmiPending || (self.status.I == 0 && (self.irqLine || self.bus.irq()));
+    }
+    
+    //first cycle of an instruction: starts a pending interupt or reads the opcode
+    fn fetch(&mut self) -> Result<(), CpuError>{
+        if let Some(fault) = &self.fault{
+            if !self.resetPending{
+                return Err(fault.clone());
             }
+            self.fault = None;
         }
-        
+    
+        let interupt = if self.resetPending{
+            self.resetPending = false;
+            Some(Interupt::RES)
+        } else if self.polledBefore && self.nmiPending{
+            self.nmiPending = false;
+            Some(Interupt::NMI)
+        } else if self.polledBefore{
+            Some(Interupt::IRQ)
+        } else {
+            None
+        };
+        if interupt.is_some(){
+            self.read(self.pc); //the opcode is fetched but thrown away
+            self.interupt = interupt;
+            self.step = 1;
+            return Ok(());
+        }
+    
         self.oldPC = self.pc;
-        let opcode = self.pcRead();
-        
-        match opcode{
-            0x00 => self.Implied(Self::BRK),
-            0x01 => self.ZeroPageIndexedXIndirect(Self::ORA),
-            0x05 => self.ZeroPage(Self::ORA),
-            0x06 => self.ZeroPage(Self::ASL),
-            0x08 => self.Implied(Self::PHP),
-            0x09 => self.Immediate(Self::ORA),
-            0x0A => self.Accumulator(Self::ASL),
-            0x0D => self.AbsoluteAdr(Self::ORA),
-            0x0E => self.AbsoluteAdr(Self::ASL),
-            0x10 => self.Relative(Self::BPL),
-            0x11 => self.ZeroPageIndirectIndexedY(Self::ORA),
-            0x15 => self.ZeroPageIndexedX(Self::ORA),
-            0x16 => self.ZeroPageIndexedX(Self::ASL),
-            0x18 => self.Implied(Self::CLC),
-            0x19 => self.AbsoluteY(Self::ORA),
-            0x1D => self.AbsoluteX(Self::ORA),
-            0x1E => self.AbsoluteX(Self::ASL),
-            0x20 => self.Implied(Self::JSR),
-            0x21 => self.ZeroPageIndexedXIndirect(Self::AND),
-            0x24 => self.ZeroPage(Self::BIT),
-            0x25 => self.ZeroPage(Self::AND),
-            0x26 => self.ZeroPageRMW(Self::ROL),
-            0x28 => self.Implied(Self::PLP),
-            0x29 => self.Immediate(Self::AND),
-            0x2A => self.AccumulatorRMW(Self::ROL),
-            0x2C => self.AbsoluteAdr(Self::BIT),
-            0x2D => self.AbsoluteAdr(Self::AND),
-            0x2E => self.AbsoluteRMW(Self::ROL),
-            0x30 => self.Relative(Self::BMI),
-            0x31 => self.ZeroPageIndirectIndexedY(Self::AND),
-            0x35 => self.ZeroPageIndexedX(Self::AND),
-            0x36 => self.ZeroPageIndexedXRMW(Self::ROL),
-            0x38 => self.Implied(Self::SEC),
-            0x39 => self.AbsoluteY(Self::AND),
-            0x3D => self.AbsoluteX(Self::AND),
-            0x3E => self.AbsoluteXRMW(Self::ROL),
-            0x40 => self.Implied(Self::RTI),
-            0x41 => self.ZeroPageIndexedXIndirect(Self::EOR),
-            0x45 => self.ZeroPage(Self::EOR),
-            0x46 => self.ZeroPageRMW(Self::LSR),
-            0x48 => self.Implied(Self::PHA),
-            0x49 => self.Immediate(Self::EOR),
-            0x4A => self.AccumulatorRMW(Self::LSR),
-            0x4C => self.Implied(Self::JMP_Absolute),
-            0x4D => self.AbsoluteAdr(Self::EOR),
-            0x4E => self.AbsoluteRMW(Self::LSR),
-            0x50 => self.Relative(Self::BVC),
-            0x51 => self.ZeroPageIndirectIndexedY(Self::EOR),
-            0x55 => self.ZeroPageIndexedX(Self::EOR),
-            0x56 => self.ZeroPageIndexedXRMW(Self::LSR),
-            0x58 => self.Implied(Self::CLI),
-            0x59 => self.AbsoluteY(Self::EOR),
-            0x5D => self.AbsoluteX(Self::EOR),
-            0x5E => self.AbsoluteXRMW(Self::LSR),
-            0x60 => self.Implied(Self::RTS),
-            0x61 => self.ZeroPageIndexedXIndirect(Self::ADC),
-            0x65 => self.ZeroPage(Self::ADC),
-            0x66 => self.ZeroPageRMW(Self::ROR),
-            0x68 => self.Implied(Self::PLA),
-            0x69 => self.Immediate(Self::ADC),
-            0x6A => self.AccumulatorRMW(Self::ROR),
-            0x6C => self.Implied(Self::JMP_Indirect),
-            0x6D => self.AbsoluteAdr(Self::ADC),
-            0x6E => self.AbsoluteRMW(Self::ROR),
-            0x70 => self.Relative(Self::BVS),
-            0x71 => self.ZeroPageIndirectIndexedY(Self::ADC),
-            0x75 => self.ZeroPageIndexedX(Self::ADC),
-            0x76 => self.ZeroPageIndexedXRMW(Self::ROR),
-            0x78 => self.Implied(Self::SEI),
-            0x79 => self.AbsoluteY(Self::ADC),
-            0x7D => self.AbsoluteX(Self::ADC),
-            0x7E => self.AbsoluteXRMW(Self::ROR),
-            0x81 => self.ZeroPageIndexedXIndirectWrite(Self::STA),
-            0x84 => self.ZeroPageWrite(Self::STY),
-            0x85 => self.ZeroPageWrite(Self::STA),
-            0x86 => self.ZeroPageWrite(Self::STX),
-            0x88 => self.Implied(Self::DEY),
-            0x8A => self.Implied(Self::TXA),
-            0x8D => self.AbsoluteWrite(Self::STA),
-            0x8E => self.AbsoluteWrite(Self::STX),
-            0x8C => self.AbsoluteWrite(Self::STY),
-            0x90 => self.Relative(Self::BCC),
-            0x91 => self.ZeroPageIndirectIndexedYWrite(Self::STA),
-            0x94 => self.ZeroPageIndexedXWrite(Self::STY),
-            0x95 => self.ZeroPageIndexedXWrite(Self::STA),
-            0x96 => self.ZeroPageIndexedYWrite(Self::STX),
-            0x98 => self.Implied(Self::TYA),
-            0x99 => self.AbsoluteYWrite(Self::STA),
-            0x9A => self.Implied(Self::TXS),
-            0x9D => self.AbsoluteXWrite(Self::STA),
-            0xA0 => self.Immediate(Self::LDY),
-            0xA1 => self.ZeroPageIndexedXIndirect(Self::LDA),
-            0xA2 => self.Immediate(Self::LDX),
-            0xA4 => self.ZeroPage(Self::LDY),
-            0xA5 => self.ZeroPage(Self::LDA),
-            0xA6 => self.ZeroPage(Self::LDX),
-            0xA8 => self.Implied(Self::TAY),
-            0xA9 => self.Immediate(Self::LDA),
-            0xAA => self.Implied(Self::TAX),
-            0xAC => self.AbsoluteAdr(Self::LDY),
-            0xAD => self.AbsoluteAdr(Self::LDA),
-            0xAE => self.AbsoluteAdr(Self::LDX),
-            0xB0 => self.Relative(Self::BCS),
-            0xB1 => self.ZeroPageIndirectIndexedY(Self::LDA),
-            0xB4 => self.ZeroPageIndexedX(Self::LDY),
-            0xB5 => self.ZeroPageIndexedX(Self::LDA),
-            0xB6 => self.ZeroPageIndexedY(Self::LDX),
-            0xB8 => self.Implied(Self::CLV),
-            0xB9 => self.AbsoluteY(Self::LDA),
-            0xBA => self.Implied(Self::TSX),
-            0xBC => self.AbsoluteX(Self::LDY),
-            0xBD => self.AbsoluteX(Self::LDA),
-            0xBE => self.AbsoluteY(Self::LDX),
-            0xC0 => self.Immediate(Self::CPY),
-            0xC1 => self.ZeroPageIndexedXIndirect(Self::CMP),
-            0xC4 => self.ZeroPage(Self::CPY),
-            0xC5 => self.ZeroPage(Self::CMP),
-            0xC6 => self.ZeroPageRMW(Self::DEC),
-            0xC8 => self.Implied(Self::INY),
-            0xC9 => self.Immediate(Self::CMP),
-            0xCA => self.Implied(Self::DEX),
-            0xCC => self.AbsoluteAdr(Self::CPY),
-            0xCD => self.AbsoluteAdr(Self::CMP),
-            0xCE => self.AbsoluteRMW(Self::DEC),
-            0xD0 => self.Relative(Self::BNE),
-            0xD1 => self.ZeroPageIndirectIndexedY(Self::CMP),
-            0xD5 => self.ZeroPageIndexedX(Self::CMP),
-            0xD6 => self.ZeroPageIndexedXRMW(Self::DEC),
-            0xD8 => self.Implied(Self::CLD),
-            0xD9 => self.AbsoluteY(Self::CMP),
-            0xDD => self.AbsoluteX(Self::CMP),
-            0xDE => self.AbsoluteXRMW(Self::DEC),
-            0xE0 => self.Immediate(Self::CPX),
-            0xE1 => self.ZeroPageIndexedXIndirect(Self::SBC),
-            0xE4 => self.ZeroPage(Self::CPX),
-            0xE5 => self.ZeroPage(Self::SBC),
-            0xE6 => self.ZeroPageRMW(Self::INC),
-            0xE8 => self.Implied(Self::INX),
-            0xE9 => self.Immediate(Self::SBC),
-            0xEA => self.Implied(Self::NOP),
-            0xEC => self.AbsoluteAdr(Self::CPX),
-            0xED => self.AbsoluteAdr(Self::SBC),
-            0xEE => self.AbsoluteRMW(Self::INC),
-            0xF0 => self.Relative(Self::BEQ),
-            0xF1 => self.ZeroPageIndirectIndexedY(Self::SBC),
-            0xF5 => self.ZeroPageIndexedX(Self::SBC),
-            0xF6 => self.ZeroPageIndexedXRMW(Self::INC),
-            0xF8 => self.Implied(Self::SED),
-            0xF9 => self.AbsoluteY(Self::SBC),
-            0xFD => self.AbsoluteX(Self::SBC),
-            0xFE => self.AbsoluteXRMW(Self::INC),
-            _=> {
-                println!("Undefined opcode !! {opcode}");
-            }
+        self.ir = self.pcRead();
+        self.step = 1;
+        self.pageCrossed = false;
+    
+        if OPCODES[self.ir as usize].mnemonic.isUndefined(){
+            return self.undefinedOpcode();
+        }
+        Ok(())
+    }
+    
+    fn execute(&mut self){
+        let opcode = &OPCODES[self.ir as usize];
+        let inst = Self::instruction(opcode.mnemonic);
+        match (opcode.mnemonic, opcode.mode){
+            (BRK, _) => self.BRK(),
+            (JSR, _) => self.JSR(),
+            (RTS, _) => self.RTS(),
+            (RTI, _) => self.RTI(),
+            (JMP, AdrMode::Absolute) => self.JMP_Absolute(),
+            (JMP, _) => self.JMP_Indirect(),
+            (PHA | PHP, _) => self.Push(inst),
+            (PLA | PLP, _) => self.Pull(inst),
+            (_, AdrMode::Implied) => self.Implied(inst),
+            (_, AdrMode::Accumulator) => self.AccumulatorRMW(inst),
+            (_, AdrMode::Immediate) => self.Immediate(inst),
+            (_, AdrMode::Relative) => self.Relative(inst),
+            //undefined opcodes only get here as NOPs, they must not write
+            (mnemonic, mode) => self.Memory(inst, mode, if mnemonic.isUndefined() {Access::Read} else {mnemonic.access()}),
+        }
+    }
+    
+    fn instruction(mnemonic: Mnemonic) -> fn(&mut Self){
+        match mnemonic{
+            ADC => Self::ADC, ALR => Self::ALR, ANC => Self::ANC, AND => Self::AND, ARR => Self::ARR,
+            ASL => Self::ASL, AXS => Self::AXS, BCC => Self::BCC, BCS => Self::BCS, BEQ => Self::BEQ,
+            BIT => Self::BIT, BMI => Self::BMI, BNE => Self::BNE, BPL => Self::BPL, BVC => Self::BVC,
+            BVS => Self::BVS, CLC => Self::CLC, CLD => Self::CLD, CLI => Self::CLI, CLV => Self::CLV,
+            CMP => Self::CMP, CPX => Self::CPX, CPY => Self::CPY, DCP => Self::DCP, DEC => Self::DEC,
+            DEX => Self::DEX, DEY => Self::DEY, EOR => Self::EOR, INC => Self::INC, INX => Self::INX,
+            INY => Self::INY, ISC => Self::ISC, LAX => Self::LAX, LDA => Self::LDA, LDX => Self::LDX,
+            LDY => Self::LDY, LSR => Self::LSR, ORA => Self::ORA, PHA => Self::PHA, PHP => Self::PHP,
+            PLA => Self::PLA, PLP => Self::PLP, RLA => Self::RLA, ROL => Self::ROL, ROR => Self::ROR,
+            RRA => Self::RRA, SAX => Self::SAX, SBC => Self::SBC, SEC => Self::SEC, SED => Self::SED,
+            SEI => Self::SEI, SLO => Self::SLO, SRE => Self::SRE, STA => Self::STA, STX => Self::STX,
+            STY => Self::STY, TAX => Self::TAX, TAY => Self::TAY, TSX => Self::TSX, TXA => Self::TXA,
+            TXS => Self::TXS, TYA => Self::TYA,
+            _ => Self::NOP, //undefined opcodes, and the ones with their own cycle sequence
         }
-        
-        self.cycles-=1; //consume the current cycle
     }
     
-    pub fn triggerInterupt(&mut self, interupt: Interupt){
-        if self.interupts.contains(&interupt){
-            return; //don't count it twice
+    fn undefinedOpcode(&mut self) -> Result<(), CpuError>{
+        if self.undefinedPolicy == UndefinedOpcodePolicy::Nop || (self.undefinedPolicy == UndefinedOpcodePolicy::Trap && self.skipTrap){
+            self.skipTrap = false;
+            return Ok(()); //execute runs it as a NOP
         }
-        self.interupts.push_back(interupt);
+    
+        let pc = self.oldPC;
+        let bytes = [self.ir, self.bus.peek(pc.wrapping_add(1)), self.bus.peek(pc.wrapping_add(2))];
+        self.pc = self.oldPC;
+        self.step = 0;
+    
+        if self.undefinedPolicy == UndefinedOpcodePolicy::Halt{
+            let fault = CpuError::UndefinedOpcode{pc, bytes};
+            self.fault = Some(fault.clone());
+            return Err(fault);
+        }
+        Err(CpuError::Trap{pc, bytes})
     }
     
-    pub fn triggerIRQ(&mut self){
-        self.triggerInterupt(Interupt::IRQ);
+    //IRQ is level triggered: it stays asserted until the source acknowledges it
+    #[allow(dead_code)]
+    pub fn setIRQLine(&mut self, active: bool){
+        self.irqLine = active;
     }
     
+    //NMI is edge triggered: only the transition to asserted counts
+    #[allow(dead_code)]
+    pub fn setNMILine(&mut self, active: bool){
+        self.nmiLine = active;
+    }
+    
+    //a single NMI edge, for callers without a line to drive
+    #[allow(dead_code)]
     pub fn triggerNMI(&mut self){
-        self.triggerInterupt(Interupt::NMI);
+        self.nmiPending = true;
     }
     
     pub fn triggerRES(&mut self){
-        self.triggerInterupt(Interupt::RES);
+        self.resetPending = true;
+    }
+    
+    //last cycle of the instruction, the next tick fetches an opcode
+    fn finish(&mut self){
+        debug_assert!(self.interupt.is_some() || self.tableCycles().is_none_or(|cycles| cycles == self.step+1),
+            "{:02X} took {} cycles, the opcode table says {:?}", self.ir, self.step+1, self.tableCycles());
+        self.step = 0;
+        self.takeBranch = false;
     }
     
+    //cycles of the current instruction according to the opcode table, None for the undefined opcodes run as NOPs
+    fn tableCycles(&self) -> Option<u8>{
+        let opcode = &OPCODES[self.ir as usize];
+        if opcode.mnemonic.isUndefined(){
+            return None;
+        }
+        Some(opcode.cycles + self.takeBranch as u8 + (opcode.pageCross && self.pageCrossed) as u8)
+    }
+    
+    fn read(&mut self, adr: u16) -> u8{
+        self.bus.read(adr)
+    }
+    
+    fn write(&mut self, adr: u16, data: u8){
+        self.bus.write(adr, data);
+    }
     
     fn updateNZFlags(&mut self, data:u8){
         self.status.N = data >> 7;
@@ -338,26 +474,70 @@ impl<'a> CPU6502<'a>{
     }
     
     fn push(&mut self, data: u8){
-        self.bus.write(to16(1, self.sp), data);
+        self.write(to16(1, self.sp), data);
         self.sp-=1;
     }
     
     fn pop(&mut self) -> u8{
         self.sp+=1;
-        self.bus.read(to16(1, self.sp))
+        self.read(to16(1, self.sp))
     }
     
-    fn pushPC(&mut self){
-        self.push((((self.pc)>>8)&255) as u8);
-        self.push((self.pc&255) as u8);
+    //reads the current top of the stack without pulling it
+    fn stackRead(&mut self){
+        self.read(to16(1, self.sp));
     }
     
-    fn setOpcode(&mut self, op: &str){
-        self.opcode = op.to_owned()
+    fn vector(interupt: Interupt) -> u16{
+        match interupt{
+            Interupt::NMI => 0xFFFA,
+            Interupt::RES => 0xFFFC,
+            Interupt::BRK | Interupt::IRQ => 0xFFFE,
+        }
     }
     
+    //7 cycles shared by BRK and the hardware interupts, the opcode fetch is the first one
+    fn Interupt(&mut self){
+        let interupt = self.interupt.unwrap();
+        match self.step{
+            1 => {self.read(self.pc);}, //BRK reads its padding byte here instead
+            //RES goes through the same steps with the writes turned into reads
+            2..=4 if interupt == Interupt::RES => {
+                self.stackRead();
+                self.sp-=1;
+            }
+            2 => self.push((self.pc>>8) as u8),
+            3 => self.push(self.pc as u8),
+            4 => {
+                self.push(self.status.asU8(interupt == Interupt::BRK));
+                //an NMI detected during the first four cycles hijacks BRK and IRQ, B stays as pushed
+                if self.nmiPending{
+                    self.nmiPending = false;
+                    self.interupt = Some(Interupt::NMI);
+                }
+            }
+            5 => {
+                self.adr = self.read(Self::vector(self.interupt.unwrap())) as u16;
+                self.status.I = 1;
+            }
+            _ => {
+                self.pc = to16(self.read(Self::vector(self.interupt.unwrap())+1), self.adr as u8);
+                self.polled = false; //no polling during the sequence, the handler runs at least one instruction
+                self.finish();
+                self.interupt = None;
+            }
+        }
+    }
     fn ADC(&mut self){
-        self.setOpcode("ADC");
+        if self.decimalMode(){
+            self.addDecimal();
+        }
+        else {
+            self.addBinary();
+        }
+    }
+    
+    fn addBinary(&mut self){
         let data = self.acc as usize +self.buffer as usize+self.status.C as usize;
         self.status.C = (data>>8) as u8;
         
@@ -365,50 +545,59 @@ impl<'a> CPU6502<'a>{
         let signB = self.buffer>>7;
         let signRes = ((data>>7)&1) as u8;
         
-        if signA == signB{
-            self.status.V = (signA != signRes) as u8;
-        }
+        self.status.V = (signA == signB && signA != signRes) as u8;
         self.acc = data as u8;
         self.updateNZFlags(self.acc);
+    }
+    
+    //NMOS behavior: Z comes from the binary sum, N and V from the sum before the high nibble is adjusted
+    fn addDecimal(&mut self){
+        let a = self.acc as i16;
+        let b = self.buffer as i16;
+        let binary = (a + b + self.status.C as i16) as u8;
         
+        let mut low = (a & 0x0F) + (b & 0x0F) + self.status.C as i16;
+        if low >= 0x0A{
+            low = ((low + 0x06) & 0x0F) + 0x10;
+        }
+        let mut sum = (a & 0xF0) + (b & 0xF0) + low;
+        let signedSum = (a & 0xF0) as u8 as i8 as i16 + (b & 0xF0) as u8 as i8 as i16 + low;
+        
+        self.status.N = ((sum >> 7) & 1) as u8;
+        self.status.V = !(-128..=127).contains(&signedSum) as u8;
+        
+        if sum >= 0xA0{
+            sum += 0x60;
+        }
+        self.status.C = (sum >= 0x100) as u8;
+        self.status.Z = (binary == 0) as u8;
+        self.acc = sum as u8;
     }
     
     fn AND(&mut self){
-        self.setOpcode("AND");
         self.acc&=self.buffer;
         self.updateNZFlags(self.acc);
     }
     
     fn ASL(&mut self){
-        self.setOpcode("ASL");
-        let c = self.buffer>>7;
-        self.status.C = c;
-        self.acc<<=1;
-        self.updateNZFlags(self.acc);
-        self.cycles+=2;
+        self.status.C = self.buffer>>7;
+        self.buffer<<=1;
+        self.updateNZFlags(self.buffer);
     }
     
     fn BCC(&mut self){
-        self.setOpcode("BCC");
-        
         self.takeBranch = self.status.C == 0;
     }
     
     fn BCS(&mut self){
-        self.setOpcode("BCS");
-        
         self.takeBranch = self.status.C == 1;
     }
     
     fn BEQ(&mut self){
-        self.setOpcode("BEQ");
-        
         self.takeBranch = self.status.Z == 1;
     }
     
     fn BIT(&mut self){
-        self.setOpcode("BIT");
-        
         self.status.N = (self.buffer>>7)&1;
         self.status.V = (self.buffer>>6)&1;
         
@@ -416,273 +605,193 @@ impl<'a> CPU6502<'a>{
     }
     
     fn BMI(&mut self){
-        self.setOpcode("BMI");
-        
         self.takeBranch = self.status.N == 1;
     }
     
     fn BNE(&mut self){
-        self.setOpcode("BNE");
-        
         self.takeBranch = self.status.Z == 0;
     }
     
     fn BPL(&mut self){
-        self.setOpcode("BPL");
-        
         self.takeBranch = self.status.N == 0;
     }
     
     fn BRK(&mut self){
-        self.setOpcode("BRK");
-        self.cycles = 1; //trigger the interupt right away
-        
-        self.pc+=1; //ignores the param
-        /*self.pushPC();//svss blabla
-        
-        let reg = self.status.asU8();
-        self.push(reg|(1u8<<4));//push with break flag set
-        
-        //self.status.I = 1;
-        */
-        self.triggerInterupt(Interupt::BRK);
+        self.pcRead(); //ignores the param
+        self.interupt = Some(Interupt::BRK);
     }
     
     fn BVC(&mut self){
-        self.setOpcode("BVC");
-        
         self.takeBranch = self.status.V == 0;
     }
     
     fn BVS(&mut self){
-        self.setOpcode("BVS");
-        
         self.takeBranch = self.status.V == 1;
     }
     
     fn CLC(&mut self){
-        self.setOpcode("CLC");
-        
         self.status.C = 0;
     }
     
     fn CLD(&mut self){
-        self.setOpcode("CLD");
-        
         self.status.D = 0;
     }
     
     fn CLI(&mut self){
-        self.setOpcode("CLI");
-        
         self.status.I = 0;
     }
     
     fn CLV(&mut self){
-        self.setOpcode("CLV");
-        
         self.status.V = 0;
     }
     
     fn CMP(&mut self){
-        self.setOpcode("CMP");
-        
         self.status.C = if self.acc >= self.buffer{1}else{0};
         let res = self.acc - self.buffer;
         self.updateNZFlags(res);
     }
     
     fn CPX(&mut self){
-        self.setOpcode("CPX");
-        
         self.status.C = if self.x >= self.buffer{1}else{0};
         let res = self.x - self.buffer;
         self.updateNZFlags(res);
     }
     
     fn CPY(&mut self){
-        self.setOpcode("CPY");
-        
         self.status.C = if self.y >= self.buffer{1}else{0};
         let res = self.y - self.buffer;
         self.updateNZFlags(res);
     }
     
     fn DEC(&mut self){
-        self.setOpcode("DEC");
-        
         self.buffer -=1;
         self.updateNZFlags(self.buffer);
     }
     
     fn DEX(&mut self){
-        self.setOpcode("DEX");
         self.x -=1;
         
         self.updateNZFlags(self.x);
     }
     
     fn DEY(&mut self){
-        self.setOpcode("DEY");
         self.y -=1;
         
         self.updateNZFlags(self.y);
     }
     
     fn EOR(&mut self){
-        self.setOpcode("EOR");
-        
         self.acc ^= self.buffer;
         
         self.updateNZFlags(self.acc);
     }
     
     fn INC(&mut self){
-        self.setOpcode("INC");
-        
         self.buffer +=1;
         self.updateNZFlags(self.buffer);
     }
     
     fn INX(&mut self){
-        self.setOpcode("INX");
         self.x +=1;
         
         self.updateNZFlags(self.x);
     }
     
     fn INY(&mut self){
-        self.setOpcode("INY");
         self.y +=1;
         
         self.updateNZFlags(self.y);
     }
     
     fn JMP_Absolute(&mut self){
-        self.setOpcode("JMP");
-        self.cycles = 3;
-        
-        let adrLow = self.pcRead();
-        let adrHigh = self.pcRead();
-        let adr = to16(adrHigh, adrLow);
-        
-        self.adrMode = adr.to_string();
-        
-        self.pc = adr;
+        match self.step{
+            1 => self.adr = self.pcRead() as u16,
+            _ => {
+                self.pc = to16(self.pcRead(), self.adr as u8);
+                self.finish();
+            }
+        }
     }
     
     fn JMP_Indirect(&mut self){
-        self.setOpcode("JMP");
-        self.cycles = 5;
-        
-        let adrLow = self.pcRead();
-        let adrHigh = self.pcRead();
-        let adr = to16(adrHigh, adrLow);
-        
-        self.adrMode = "(".to_owned()+&adr.to_string()+")";
-        
-        let adrLow = self.bus.read(adr);
-        let adrHigh = self.bus.read(adr+1);
-        self.pc = to16(adrHigh, adrLow);
+        match self.step{
+            1 => self.adr = self.pcRead() as u16,
+            2 => {
+                self.adr = to16(self.pcRead(), self.adr as u8);
+            }
+            3 => self.buffer = self.read(self.adr),
+            _ => {
+                //the pointer high byte is read without carrying into the page
+                let high = self.read((self.adr & 0xFF00) | (self.adr+1) & 0x00FF);
+                self.pc = to16(high, self.buffer);
+                self.finish();
+            }
+        }
     }
     
     fn JSR(&mut self){
-        self.setOpcode("JSR");
-        self.cycles = 6;
-        
-        let adrLow = self.pcRead();
-        let adrHigh = self.pcRead();
-        let adr = to16(adrHigh, adrLow);
-        
-        self.adrMode = adr.to_string();
-        
-        self.pushPC();
-        
-        self.pc = adr;
+        match self.step{
+            1 => self.adr = self.pcRead() as u16,
+            2 => self.stackRead(),
+            3 => self.push((self.pc>>8) as u8), //pc points to the last byte of the instruction
+            4 => self.push(self.pc as u8),
+            _ => {
+                self.pc = to16(self.read(self.pc), self.adr as u8);
+                self.finish();
+            }
+        }
     }
     
     fn LDA(&mut self){
-        self.setOpcode("LDA");
-        
         self.acc = self.buffer;
         
         self.updateNZFlags(self.acc);
     }
     
     fn LDX(&mut self){
-        self.setOpcode("LDX");
-        
         self.x = self.buffer;
         
         self.updateNZFlags(self.x);
     }
     
     fn LDY(&mut self){
-        self.setOpcode("LDY");
-        
         self.y = self.buffer;
         
         self.updateNZFlags(self.y);
     }
     
     fn LSR(&mut self){
-        self.setOpcode("LSR");
-        
         self.status.C = self.buffer&1;
         self.buffer>>=1;
         
         self.updateNZFlags(self.buffer);
     }
     
-    fn NOP(&mut self){
-        self.setOpcode("NOP");
-    }
+    fn NOP(&mut self){}
     
     fn ORA(&mut self){
-        self.setOpcode("ORA");
-        
         self.acc |= self.buffer;
         self.updateNZFlags(self.acc);
     }
     
     fn PHA(&mut self){
-        self.setOpcode("PHA");
-        
-        self.push(self.acc);
-        
-        self.cycles+=1;
+        self.buffer = self.acc;
     }
     
     fn PHP(&mut self){
-        self.setOpcode("PHP");
-        
-        let status = self.status.asU8();// | (1u8<<5)|(1u8<<4);//set bit 5 and break flag to 1
-        self.push(status);
-        
-        self.cycles+=1;
+        self.buffer = self.status.asU8(true);
     }
     
     fn PLA(&mut self){
-        self.setOpcode("PLA");
-        
-        self.acc = self.pop();
+        self.acc = self.buffer;
         self.updateNZFlags(self.acc);
-        
-        self.cycles+=2;
     }
     
     fn PLP(&mut self){
-        self.setOpcode("PLP");
-        
-        let reg = self.pop();
-        self.status.fromU8(reg);
-        
-        self.cycles+=2;
+        self.status.fromU8(self.buffer);
     }
     
     fn ROL(&mut self){
-        self.setOpcode("ROL");
-        
         let tmpC = self.status.C;
         self.status.C = (self.buffer>>7)&1;
         self.buffer<<=1;
@@ -692,8 +801,6 @@ impl<'a> CPU6502<'a>{
     }
     
     fn ROR(&mut self){
-        self.setOpcode("ROR");
-        
         let tmpC = self.status.C;
         self.status.C = self.buffer&1;
         self.buffer>>=1;
@@ -703,465 +810,879 @@ impl<'a> CPU6502<'a>{
     }
     
     fn RTI(&mut self){
-        self.setOpcode("RTI");
-        self.cycles = 6;
-        
-        let reg = self.pop();
-        self.status.fromU8(reg);
-        
-        let adrLow = self.pop();
-        let adrHigh = self.pop();
-        
-        self.pc = to16(adrHigh, adrLow);
+        match self.step{
+            1 => {self.read(self.pc);},
+            2 => self.stackRead(),
+            3 => {
+                let reg = self.pop();
+                self.status.fromU8(reg);
+            }
+            4 => self.adr = self.pop() as u16,
+            _ => {
+                self.pc = to16(self.pop(), self.adr as u8);
+                self.finish();
+            }
+        }
     }
     
     fn RTS(&mut self){
-        self.setOpcode("RTS");
-        self.cycles = 6;
-        
-        let adrLow = self.pop();
-        let adrHigh = self.pop();
-        
-        self.pc = to16(adrHigh, adrLow);
-        self.pc += 1;
+        match self.step{
+            1 => {self.read(self.pc);},
+            2 => self.stackRead(),
+            3 => self.adr = self.pop() as u16,
+            4 => self.pc = to16(self.pop(), self.adr as u8),
+            _ => {
+                self.pcRead(); //JSR pushed the address of its last byte
+                self.finish();
+            }
+        }
     }
     
     fn SBC(&mut self){
-        if self.status.D==1{
-            //todo
-        }
-        else{
-            //setReg(idb,~getReg(idb)); //1's complement (adding the carry will make it 2's complement, not adding it will make -idb-1(borrow)
-            self.buffer = !self.buffer;
+        let acc = self.acc;
+        let operand = self.buffer;
+        let carry = self.status.C as i16;
+        
+        //1's complement (adding the carry will make it 2's complement, not adding it will make -idb-1(borrow)
+        self.buffer = !self.buffer;
+        self.addBinary(); //the NMOS part sets all the flags from the binary result, even in decimal mode
+        
+        if self.decimalMode(){
+            let a = acc as i16;
+            let b = operand as i16;
+            let mut low = (a & 0x0F) - (b & 0x0F) + carry - 1;
+            if low < 0{
+                low = ((low - 0x06) & 0x0F) - 0x10;
+            }
+            let mut result = (a & 0xF0) - (b & 0xF0) + low;
+            if result < 0{
+                result -= 0x60;
+            }
+            self.acc = result as u8;
         }
-        //self.acc = self.acc - self.buffer - (1-self.status.C);
-        //self.updateNZFlags(self.acc);
-        self.ADC();
-        self.setOpcode("SBC");
     }
     
     fn SEC(&mut self){
-        self.setOpcode("SEC");
-        
         self.status.C = 1;
     }
     
     fn SED(&mut self){
-        self.setOpcode("SED");
-        
         self.status.D = 1;
     }
     
     fn SEI(&mut self){
-        self.setOpcode("SEI");
-        
         self.status.I = 1;
     }
     
     fn STA(&mut self){
-        self.setOpcode("STA");
-        
         self.buffer = self.acc;
     }
     
     fn STX(&mut self){
-        self.setOpcode("STX");
-        
         self.buffer = self.x;
     }
     
     fn STY(&mut self){
-        self.setOpcode("STY");
-        
         self.buffer = self.y;
     }
     
     fn TAX(&mut self){
-        self.setOpcode("TAX");
-        
         self.x = self.acc;
         self.updateNZFlags(self.acc);
     }
     
     fn TAY(&mut self){
-        self.setOpcode("TAY");
-        
         self.y = self.acc;
         self.updateNZFlags(self.acc);
     }
     
     fn TSX(&mut self){
-        self.setOpcode("TSX");
-        
         self.x = self.sp;
         self.updateNZFlags(self.sp);
     }
     
     fn TXA(&mut self){
-        self.setOpcode("TXA");
-        
         self.acc = self.x;
         self.updateNZFlags(self.acc);
     }
     
     fn TXS(&mut self){
-        self.setOpcode("TXS");
-        
         self.sp = self.x;
-        self.updateNZFlags(self.x);
     }
     
     fn TYA(&mut self){
-        self.setOpcode("TYA");
-        
         self.acc = self.y;
         self.updateNZFlags(self.acc);
     }
     
-    fn pcRead(&mut self)->u8{
-        let data = self.bus.read(self.pc);
-        self.pc+=1;
-        data
+    //unofficial opcodes
+    
+    fn ALR(&mut self){
+        self.AND();
+        self.buffer = self.acc;
+        self.LSR();
+        self.acc = self.buffer;
     }
     
-    fn handlePageCross(&mut self, adr:usize, data: u8)->usize{
-        let adr = adr as u16;
-        if ((adr&255)+data as u16)>>8 == 1{
-            self.cycles+=1;
-        }
-        adr as usize+data as usize
+    fn ANC(&mut self){
+        self.AND();
+        self.status.C = self.status.N;
     }
     
-    fn AbsoluteAdr(&mut self, inst:fn (&mut Self)->()){
-        self.cycles = 4;
-        
-        let adrLow = self.pcRead();
-        let adrHigh = self.pcRead();
-        let adr = to16(adrHigh, adrLow);
-        
-        self.adrMode = format!("{adr:#x}");//adr.to_string();
-        
-        self.buffer = self.bus.read(adr);
+    fn ARR(&mut self){
+        let data = self.acc & self.buffer;
+        self.acc = (data>>1) | (self.status.C<<7);
+        self.updateNZFlags(self.acc);
         
-        inst(self);
+        if self.decimalMode(){
+            //N and Z come from the binary result, then each nibble gets the BCD fixup
+            self.status.N = self.status.C;
+            self.status.V = ((data ^ self.acc)>>6)&1;
+            if (data & 0x0F) + (data & 0x01) > 5{
+                self.acc = (self.acc & 0xF0) | ((self.acc + 6) & 0x0F);
+            }
+            if (data & 0xF0) as u16 + (data & 0x10) as u16 > 0x50{
+                self.acc += 0x60;
+                self.status.C = 1;
+            }
+            else {
+                self.status.C = 0;
+            }
+        }
+        else {
+            self.status.C = (self.acc>>6)&1;
+            self.status.V = ((self.acc>>6) ^ (self.acc>>5))&1;
+        }
     }
     
-    fn AbsoluteRMW(&mut self, inst:fn (&mut Self)->()){
-        self.cycles = 6;
-        
-        let adrLow = self.pcRead();
-        let adrHigh = self.pcRead();
-        let adr = to16(adrHigh, adrLow);
-        
-        self.adrMode = adr.to_string();
-        
-        self.buffer = self.bus.read(adr);
-        
-        inst(self);
-        
-        self.bus.write(adr, self.buffer);
+    fn AXS(&mut self){
+        let data = self.acc & self.x;
+        self.status.C = if data >= self.buffer{1}else{0};
+        self.x = data - self.buffer;
+        self.updateNZFlags(self.x);
     }
     
-    fn AbsoluteWrite(&mut self, inst:fn (&mut Self)->()){
-        self.cycles = 4;
-        inst(self);
-        
-        let adrLow = self.pcRead();
-        let adrHigh = self.pcRead();
-        let adr = to16(adrHigh, adrLow);
-        
-        self.adrMode = adr.to_string();
-        
-        self.bus.write(adr, self.buffer);
-        
+    fn DCP(&mut self){
+        self.DEC();
+        self.CMP();
     }
     
-    fn AbsoluteX(&mut self, inst:fn (&mut Self)->()){
-        self.cycles = 4;
-        
-        let adrLow = self.pcRead();
-        let adrHigh = self.pcRead();
-        let baseAdr = to16(adrHigh, adrLow);
-        
-        self.adrMode = baseAdr.to_string()+",X";
-        
-        let adr = self.handlePageCross(baseAdr,self.x);
-        self.buffer = self.bus.read(adr);
-        
-        inst(self);
+    fn ISC(&mut self){
+        self.INC();
+        let data = self.buffer;
+        self.SBC();
+        self.buffer = data; //SBC complements the buffer, the incremented value is what gets written back
     }
     
-    fn AbsoluteXRMW(&mut self, inst:fn (&mut Self)->()){
-        self.cycles = 7;
-        
-        let adrLow = self.pcRead();
-        let adrHigh = self.pcRead();
-        let baseAdr = to16(adrHigh, adrLow);
-        
-        self.adrMode = baseAdr.to_string()+",X";
-        
-        let adr = baseAdr + (self.x as usize);
-        self.buffer = self.bus.read(adr);
-        
-        inst(self);
-        
-        self.bus.write(adr, self.buffer);
+    fn LAX(&mut self){
+        self.LDA();
+        self.x = self.acc;
     }
     
-    fn AbsoluteXWrite(&mut self, inst:fn (&mut Self)->()){
-        self.cycles = 5;
-        inst(self);
-        
-        let adrLow = self.pcRead();
-        let adrHigh = self.pcRead();
-        let baseAdr = to16(adrHigh, adrLow);
-        
-        self.adrMode = baseAdr.to_string()+",X";
-        
-        let adr = baseAdr + (self.x as usize);
-        self.bus.write(adr, self.buffer);
+    fn RLA(&mut self){
+        self.ROL();
+        self.AND();
     }
     
-    fn AbsoluteY(&mut self, inst:fn (&mut Self)->()){
-        self.cycles = 4;
-        
-        let adrLow = self.pcRead();
-        let adrHigh = self.pcRead();
-        let baseAdr = to16(adrHigh, adrLow);
-        
-        self.adrMode = baseAdr.to_string()+",Y";
-        
-        let adr = self.handlePageCross(baseAdr,self.y);
-        self.buffer = self.bus.read(adr);
-        
-        inst(self);
+    fn RRA(&mut self){
+        self.ROR();
+        self.ADC();
     }
     
-    fn AbsoluteYWrite(&mut self, inst:fn (&mut Self)->()){
-        self.cycles = 5;
-        inst(self);
-        
-        let adrLow = self.pcRead();
-        let adrHigh = self.pcRead();
-        let baseAdr = to16(adrHigh, adrLow);
-        
-        self.adrMode = baseAdr.to_string()+",Y";
-        
-        let adr = baseAdr + (self.y as usize);
-        self.bus.write(adr, self.buffer);
+    fn SAX(&mut self){
+        self.buffer = self.acc & self.x;
     }
     
-    fn Accumulator(&mut self, inst:fn(&mut Self)->()){
-        self.cycles = 0; //always paired with instruction that add 2 cycles to the other modes
-        self.adrMode = "".to_owned();
-        
-        self.buffer = self.acc;
-        
-        inst(self);
+    fn SLO(&mut self){
+        self.ASL();
+        self.ORA();
     }
     
-    fn AccumulatorRMW(&mut self, inst:fn(&mut Self)->()){
-        self.cycles = 2; 
-        self.adrMode = "".to_owned();
-        
-        self.buffer = self.acc;
-        
-        inst(self);
-        
-        self.acc = self.buffer;
+    fn SRE(&mut self){
+        self.LSR();
+        self.EOR();
     }
     
-    fn Immediate(&mut self, inst: fn(&mut Self)->()){
-        self.cycles = 2;
-        
-        self.buffer = self.pcRead();
-        
-        self.adrMode = "#".to_owned()+&self.buffer.to_string();
-        
-        inst(self);
+    fn pcRead(&mut self)->u8{
+        let data = self.read(self.pc);
+        self.pc = self.pc.wrapping_add(1);
+        data
     }
     
-    fn Implied(&mut self, inst: fn(&mut Self)->()){
-        self.cycles = 2;
-        
-        self.adrMode = "".to_owned();
-        
-        inst(self);
+    //the addressing helpers build self.adr one cycle at a time, once it's ready they return
+    //the index of the current cycle among the memory access cycles that follow
+    
+    fn zeroPageAdr(&mut self) -> Option<u8>{
+        match self.step{
+            1 => {
+                self.adr = self.pcRead() as u16;
+                None
+            }
+            _ => Some(self.step-2),
+        }
     }
     
-    fn Relative(&mut self, inst: fn(&mut Self)->()){
-        self.cycles = 2;
-        self.buffer = self.pcRead();
-        
-        self.adrMode = self.buffer.to_string();
-        
-        inst(self);
-        
-        if self.takeBranch{
-            self.cycles+=1;
-            self.takeBranch = false; //reset it for next use
-            let high:usize = if (self.buffer>>7)&1 == 1{
-                (255<<8) as usize
-            } else{
-                0
-            };
-            
-            self.pc = (self.handlePageCross(self.pc, self.buffer)+high) & 0xFFFF;
+    fn zeroPageIndexedAdr(&mut self, index: u8) -> Option<u8>{
+        match self.step{
+            1 => {
+                self.adr = self.pcRead() as u16;
+                None
+            }
+            2 => {
+                self.read(self.adr); //reads the base address while adding the index
+                self.adr = (self.adr as u8 + index) as u16;
+                None
+            }
+            _ => Some(self.step-3),
         }
     }
     
-    fn ZeroPage(&mut self, inst: fn(&mut Self)->()){
-        self.cycles = 3;
-        
-        let offset = self.pcRead();
-        
-        self.adrMode = offset.to_string();
-        
-        self.buffer = self.bus.read(to16(0, offset));
-        
-        inst(self);
+    fn absoluteAdr(&mut self) -> Option<u8>{
+        match self.step{
+            1 => {
+                self.adr = self.pcRead() as u16;
+                None
+            }
+            2 => {
+                self.adr = to16(self.pcRead(), self.adr as u8);
+                None
+            }
+            _ => Some(self.step-3),
+        }
     }
     
-    fn ZeroPageRMW(&mut self, inst: fn(&mut Self)->()){
-        self.cycles = 5;
-        
-        let offset = self.pcRead();
-        
-        self.adrMode = offset.to_string();
-        
-        let adr = to16(0, offset);
-        self.buffer = self.bus.read(adr);
-        
-        inst(self);
-        
-        self.bus.write(adr, self.buffer);
+    //reads only pay for the fixup cycle when the page is crossed, writes and RMW always take it
+    fn absoluteIndexedAdr(&mut self, index: u8, read: bool) -> Option<u8>{
+        match self.step{
+            1 => {
+                self.adr = self.pcRead() as u16;
+                None
+            }
+            2 => {
+                let baseAdr = to16(self.pcRead(), self.adr as u8);
+                self.indexAdr(baseAdr, index);
+                None
+            }
+            3 if read && !self.pageCrossed => Some(0),
+            3 => {
+                self.fixAdr();
+                None
+            }
+            _ => Some(self.step-4),
+        }
     }
     
-    fn ZeroPageWrite(&mut self, inst: fn(&mut Self)->()){
-        self.cycles = 3;
-        inst(self);
-        
-        let offset = self.pcRead();
-        
-        self.adrMode = offset.to_string();
-        
-        self.bus.write(to16(0, offset), self.buffer);
-        
+    fn indexedIndirectAdr(&mut self) -> Option<u8>{
+        match self.step{
+            1 => {
+                self.pointer = self.pcRead();
+                None
+            }
+            2 => {
+                self.read(self.pointer as u16);
+                self.pointer += self.x;
+                None
+            }
+            3 => {
+                self.adr = self.read(self.pointer as u16) as u16;
+                None
+            }
+            4 => {
+                self.adr = to16(self.read((self.pointer+1) as u16), self.adr as u8);
+                None
+            }
+            _ => Some(self.step-5),
+        }
     }
     
-    fn ZeroPageIndexedXIndirect(&mut self, inst: fn(&mut Self)->()){
-        self.cycles = 6;
-        
-        let mut offset = self.pcRead();
-        self.adrMode = "(".to_owned()+&offset.to_string()+",X)";
-        offset += self.x;
-        
-        let adrLow = self.bus.read(to16(0, offset));
-        let adrHigh = self.bus.read(to16(0, offset+1));
-        self.buffer = self.bus.read(to16(adrHigh, adrLow));
-        
-        inst(self);
+    fn indirectIndexedAdr(&mut self, read: bool) -> Option<u8>{
+        match self.step{
+            1 => {
+                self.pointer = self.pcRead();
+                None
+            }
+            2 => {
+                self.adr = self.read(self.pointer as u16) as u16;
+                None
+            }
+            3 => {
+                let baseAdr = to16(self.read((self.pointer+1) as u16), self.adr as u8);
+                self.indexAdr(baseAdr, self.y);
+                None
+            }
+            4 if read && !self.pageCrossed => Some(0),
+            4 => {
+                self.fixAdr();
+                None
+            }
+            _ => Some(self.step-5),
+        }
     }
     
-    fn ZeroPageIndexedXIndirectWrite(&mut self, inst: fn(&mut Self)->()){
-        self.cycles = 6;
-        inst(self);
-        
-        let mut offset = self.pcRead();
-        self.adrMode = "(".to_owned()+&offset.to_string()+",X)";
-        offset += self.x;
-        
-        let adrLow = self.bus.read(to16(0, offset));
-        let adrHigh = self.bus.read(to16(0, offset+1));
-        
-        self.bus.write(to16(adrHigh, adrLow),self.buffer);
+    //adds the index to the low byte only, the carry goes to the high byte on the next cycle
+    fn indexAdr(&mut self, baseAdr: u16, index: u8){
+        let low = (baseAdr & 0xFF) + index as u16;
+        self.pageCrossed = low > 0xFF;
+        self.adr = (baseAdr & 0xFF00) | (low & 0xFF);
     }
     
-    fn ZeroPageIndirectIndexedY(&mut self, inst: fn(&mut Self)->()){
-        self.cycles = 5;
-        
-        let offset = self.pcRead();
-        self.adrMode = "(".to_owned()+&offset.to_string()+"),Y";
-        
-        let adrLow = self.bus.read(to16(0, offset));
-        let adrHigh = self.bus.read(to16(0, offset+1));
-        let adr = self.handlePageCross(to16(adrHigh, adrLow),self.y);
-        self.buffer = self.bus.read(adr);
-        
+    //dummy read at the uncorrected address
+    fn fixAdr(&mut self){
+        self.read(self.adr);
+        if self.pageCrossed{
+            self.adr = self.adr.wrapping_add(0x100);
+        }
+    }
+    
+    fn readOperand(&mut self, inst:fn (&mut Self)->()){
+        self.buffer = self.read(self.adr);
         inst(self);
+        self.finish();
     }
     
-    fn ZeroPageIndirectIndexedYWrite(&mut self, inst: fn(&mut Self)->()){
-        self.cycles = 6;
+    fn writeOperand(&mut self, inst:fn (&mut Self)->()){
         inst(self);
-        
-        let offset = self.pcRead();
-        self.adrMode = "(".to_owned()+&offset.to_string()+"),Y";
-        
-        let adrLow = self.bus.read(to16(0, offset));
-        let adrHigh = self.bus.read(to16(0, offset+1));
-        let adr = to16(adrHigh, adrLow)+(self.y as usize);
-        self.bus.write(adr, self.buffer);
+        self.write(self.adr, self.buffer);
+        self.finish();
     }
     
-    fn ZeroPageIndexedX(&mut self, inst: fn(&mut Self)->()){
-        self.cycles = 4;
-        
-        let mut offset = self.pcRead();
-        self.adrMode = offset.to_string()+",X";
-        offset+=self.x;
-        
-        self.buffer = self.bus.read(to16(0, offset));
-        
+    //the unmodified value is written back while the ALU works, then the result
+    fn readModifyWrite(&mut self, inst:fn (&mut Self)->(), step: u8){
+        match step{
+            0 => self.buffer = self.read(self.adr),
+            1 => {
+                self.write(self.adr, self.buffer);
+                inst(self);
+            }
+            _ => {
+                self.write(self.adr, self.buffer);
+                self.finish();
+            }
+        }
+    }
+    
+    //every mode that accesses memory: the addressing cycles, then the access cycles
+    fn Memory(&mut self, inst:fn (&mut Self)->(), mode: AdrMode, access: Access){
+        let read = access == Access::Read;
+        let step = match mode{
+            AdrMode::ZeroPage => self.zeroPageAdr(),
+            AdrMode::ZeroPageX => self.zeroPageIndexedAdr(self.x),
+            AdrMode::ZeroPageY => self.zeroPageIndexedAdr(self.y),
+            AdrMode::Absolute => self.absoluteAdr(),
+            AdrMode::AbsoluteX => self.absoluteIndexedAdr(self.x, read),
+            AdrMode::AbsoluteY => self.absoluteIndexedAdr(self.y, read),
+            AdrMode::IndexedIndirect => self.indexedIndirectAdr(),
+            _ => self.indirectIndexedAdr(read),
+        };
+        if let Some(step) = step{
+            match access{
+                Access::Read => self.readOperand(inst),
+                Access::Write => self.writeOperand(inst),
+                Access::RMW => self.readModifyWrite(inst, step),
+            }
+        }
+    }
+    
+    fn AccumulatorRMW(&mut self, inst:fn(&mut Self)->()){
+        self.read(self.pc);
+    
+        self.buffer = self.acc;
         inst(self);
+        self.acc = self.buffer;
+        self.finish();
     }
     
-    fn ZeroPageIndexedXRMW(&mut self, inst: fn(&mut Self)->()){
-        self.cycles = 6;
-        
-        let mut offset = self.pcRead();
-        self.adrMode = offset.to_string()+",X";
-        offset+=self.x;
-        
-        let adr = to16(0, offset);
-        self.buffer = self.bus.read(adr);
-        
+    fn Immediate(&mut self, inst: fn(&mut Self)->()){
+        self.buffer = self.pcRead();
+    
         inst(self);
-        
-        self.bus.write(adr, self.buffer);
+        self.finish();
     }
     
-    fn ZeroPageIndexedXWrite(&mut self, inst: fn(&mut Self)->()){
-        self.cycles = 4;
+    fn Implied(&mut self, inst: fn(&mut Self)->()){
+        self.read(self.pc);
+    
         inst(self);
-        
-        let mut offset = self.pcRead();
-        self.adrMode = offset.to_string()+",X";
-        offset+=self.x;
-        
-        self.bus.write(to16(0,offset), self.buffer);
+        self.finish();
+    }
+    
+    //PHA and PHP put the value to push in the buffer
+    fn Push(&mut self, inst: fn(&mut Self)->()){
+        match self.step{
+            1 => {
+                self.read(self.pc);
+            }
+            _ => {
+                inst(self);
+                self.push(self.buffer);
+                self.finish();
+            }
+        }
     }
     
-    fn ZeroPageIndexedY(&mut self, inst: fn(&mut Self)->()){
-        self.cycles = 4;
+    //PLA and PLP get the pulled value in the buffer
+    fn Pull(&mut self, inst: fn(&mut Self)->()){
+        match self.step{
+            1 => {
+                self.read(self.pc);
+            }
+            2 => self.stackRead(),
+            _ => {
+                self.buffer = self.pop();
+                inst(self);
+                self.finish();
+            }
+        }
+    }
+    
+    fn Relative(&mut self, inst: fn(&mut Self)->()){
+        match self.step{
+            1 => {
+                self.buffer = self.pcRead();
+    
+                inst(self);
+                if !self.takeBranch{
+                    self.finish();
+                }
+            }
+            2 => {
+                self.read(self.pc);
+                let target = self.pc.wrapping_add(self.buffer as i8 as u16);
+                self.pageCrossed = target & 0xFF00 != self.pc & 0xFF00;
+                self.adr = target;
+                self.pc = (self.pc & 0xFF00) | (target & 0xFF);
+                if !self.pageCrossed{
+                    self.pollSkip = true; //a taken branch that stays on the page only polls before its operand fetch
+                    self.finish();
+                }
+            }
+            _ => {
+                self.read(self.pc); //wrong page
+                self.pc = self.adr;
+                self.finish();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::Opcodes::AdrMode::*;
+
+    #[derive(Debug, PartialEq)]
+    enum Cycle{
+        Read(u16),
+        Write(u16, u8),
+    }
+
+    //64K of RAM, recording every bus cycle
+    struct TestBus{
+        memory: Vec<u8>,
+        cycles: Vec<Cycle>,
+    }
+
+    impl CpuBus for TestBus{
+        fn read(&mut self, adr: u16) -> u8{
+            self.cycles.push(Cycle::Read(adr));
+            self.memory[adr as usize]
+        }
         
-        let mut offset = self.pcRead();
-        self.adrMode = offset.to_string()+",Y";
-        offset+=self.y;
+        fn write(&mut self, adr: u16, data: u8){
+            self.cycles.push(Cycle::Write(adr, data));
+            self.memory[adr as usize] = data;
+        }
         
-        self.buffer = self.bus.read(to16(0, offset));
+        fn peek(&mut self, adr: u16) -> u8{
+            self.memory[adr as usize]
+        }
+    }
+
+    //cycles taken by the instruction at pc, with X and Y set to index
+    fn cycles(program: &[u8], pc: u16, index: u8, status: u8) -> u8{
+        let mut memory = vec![0u8; 0x10000];
+        memory[pc as usize..pc as usize + program.len()].copy_from_slice(program);
+        memory[0xFFFC] = pc as u8;
+        memory[0xFFFD] = (pc >> 8) as u8;
+        //pointer of the indirect modes, $0380 like the absolute operands
+        memory[0x10] = 0x80;
+        memory[0x11] = 0x03;
+        
+        let mut cpu = CPU6502::new(TestBus{memory, cycles: Vec::new()});
+        while !cpu.atInstructionBoundary(){
+            cpu.tick().unwrap();
+        }
+        cpu.x = index;
+        cpu.y = index;
+        cpu.status.fromU8(status);
+        
+        let mut cycles = 0;
+        loop{
+            cpu.tick().unwrap();
+            cycles += 1;
+            if cpu.atInstructionBoundary(){
+                return cycles;
+            }
+        }
+    }
+
+    //the executor has to agree with the table the disassembler and tracer use
+    #[test]
+    fn cyclesMatchOpcodeTable(){
+        for (opcode, op) in OPCODES.iter().enumerate(){
+            if op.mnemonic.isUndefined(){
+                continue;
+            }
+            let opcode = opcode as u8;
+            
+            if op.mode == Relative{
+                //one flag setting takes the branch, the other doesn't
+                let mut samePage = [cycles(&[opcode, 0x02], 0x0200, 0, 0x00), cycles(&[opcode, 0x02], 0x0200, 0, 0xFF)];
+                samePage.sort();
+                assert_eq!(samePage, [op.cycles, op.cycles + 1], "{opcode:02X} {:?}", op.mnemonic);
+                
+                //$02F2 + $7F is on the next page
+                let otherPage = cycles(&[opcode, 0x7F], 0x02F0, 0, 0x00).max(cycles(&[opcode, 0x7F], 0x02F0, 0, 0xFF));
+                assert_eq!(otherPage, op.cycles + 1 + op.pageCross as u8, "{opcode:02X} {:?}", op.mnemonic);
+                continue;
+            }
+            
+            let program = match op.mode{
+                ZeroPage | ZeroPageX | ZeroPageY | IndexedIndirect | IndirectIndexed => [opcode, 0x10, 0],
+                _ => [opcode, 0x80, 0x03],
+            };
+            assert_eq!(cycles(&program, 0x0200, 0, 0), op.cycles, "{opcode:02X} {:?} {:?}", op.mnemonic, op.mode);
+            //$0380 + $FF crosses a page
+            assert_eq!(cycles(&program, 0x0200, 0xFF, 0), op.cycles + op.pageCross as u8, "{opcode:02X} {:?} {:?} page cross", op.mnemonic, op.mode);
+        }
+    }
+
+    //program at $0200, NMI handler at $0300, IRQ/BRK handler at $0400
+    fn boot(program: &[u8]) -> CPU6502<TestBus>{
+        let mut memory = vec![0u8; 0x10000];
+        memory[0x0200..0x0200 + program.len()].copy_from_slice(program);
+        memory[0xFFFC] = 0x00;
+        memory[0xFFFD] = 0x02;
+        memory[0xFFFA] = 0x00;
+        memory[0xFFFB] = 0x03;
+        memory[0xFFFE] = 0x00;
+        memory[0xFFFF] = 0x04;
+        let mut cpu = CPU6502::new(TestBus{memory, cycles: Vec::new()});
+        while !cpu.atInstructionBoundary(){
+            cpu.tick().unwrap();
+        }
+        cpu
+    }
+
+    //an instruction, or an interupt sequence
+    fn runInstruction(cpu: &mut CPU6502<TestBus>){
+        cpu.tick().unwrap();
+        while !cpu.atInstructionBoundary(){
+            cpu.tick().unwrap();
+        }
+    }
+
+    //return address and status pushed by the last interupt sequence, with the stack at $FD before it
+    fn pushed(cpu: &CPU6502<TestBus>) -> (u16, u8){
+        let memory = &cpu.bus.memory;
+        (to16(memory[0x01FD], memory[0x01FC]), memory[0x01FB])
+    }
+
+    #[test]
+    fn interuptLines(){
+        //CLI then NOPs
+        let mut cpu = boot(&[0x58, 0xEA, 0xEA, 0xEA, 0xEA]);
+        runInstruction(&mut cpu);
+        
+        //the interupt sequence starts after the instruction that polled it.
+        //NMI only on the edge, holding the line doesn't retrigger it
+        cpu.setNMILine(true);
+        runInstruction(&mut cpu);
+        runInstruction(&mut cpu);
+        assert_eq!(cpu.pc, 0x0300);
+        cpu.pc = 0x0202;
+        runInstruction(&mut cpu);
+        runInstruction(&mut cpu);
+        assert_eq!(cpu.pc, 0x0204);
+        cpu.setNMILine(false);
+        
+        cpu.pc = 0x0202;
+        cpu.triggerNMI();
+        runInstruction(&mut cpu);
+        runInstruction(&mut cpu);
+        assert_eq!(cpu.pc, 0x0300);
+        
+        //IRQ is a level, masked by I
+        cpu.pc = 0x0202;
+        cpu.status.I = 1;
+        cpu.setIRQLine(true);
+        runInstruction(&mut cpu);
+        runInstruction(&mut cpu);
+        assert_eq!(cpu.pc, 0x0204);
+        cpu.status.I = 0;
+        runInstruction(&mut cpu);
+        runInstruction(&mut cpu);
+        assert_eq!(cpu.pc, 0x0400);
+    }
+
+    #[test]
+    fn brkPushesB(){
+        //BRK skips its padding byte
+        let mut cpu = boot(&[0x00, 0xFF]);
+        runInstruction(&mut cpu);
+        assert_eq!(cpu.pc, 0x0400);
+        assert_eq!(pushed(&cpu), (0x0202, 0x34));
+        assert_eq!(cpu.sp, 0xFA);
+        
+        //PHP
+        let mut cpu = boot(&[0x08]);
+        runInstruction(&mut cpu);
+        assert_eq!(cpu.bus.memory[0x01FD], 0x34);
+    }
+
+    #[test]
+    fn hardwareInteruptsPushBClear(){
+        let mut cpu = boot(&[0x58, 0xEA, 0xEA]);
+        runInstruction(&mut cpu);
+        cpu.setIRQLine(true);
+        runInstruction(&mut cpu);
+        runInstruction(&mut cpu);
+        assert_eq!(cpu.pc, 0x0400);
+        assert_eq!(pushed(&cpu), (0x0202, 0x20));
+        assert_eq!(cpu.status.I, 1);
+        
+        let mut cpu = boot(&[0xEA, 0xEA]);
+        cpu.triggerNMI();
+        runInstruction(&mut cpu);
+        runInstruction(&mut cpu);
+        assert_eq!(cpu.pc, 0x0300);
+        assert_eq!(pushed(&cpu), (0x0201, 0x24));
+    }
+
+    #[test]
+    fn resetDoesntWriteTheStack(){
+        let mut cpu = boot(&[0xEA, 0xEA]);
+        runInstruction(&mut cpu);
+        cpu.bus.memory[0x0100..0x0200].fill(0x55);
+        cpu.triggerRES();
+        runInstruction(&mut cpu);
+        assert_eq!(cpu.pc, 0x0200);
+        assert_eq!(cpu.sp, 0xFA);
+        assert!(cpu.bus.memory[0x0100..0x0200].iter().all(|&byte| byte == 0x55));
+    }
+
+    //CLI, SEI and PLP change I after their poll, the new I only counts one instruction later
+    #[test]
+    fn irqDelayAfterChangingI(){
+        //CLI: the NOP after it still runs
+        let mut cpu = boot(&[0x58, 0xEA, 0xEA]);
+        cpu.setIRQLine(true);
+        runInstruction(&mut cpu);
+        runInstruction(&mut cpu);
+        assert_eq!(cpu.pc, 0x0202);
+        runInstruction(&mut cpu);
+        assert_eq!(cpu.pc, 0x0400);
+        assert_eq!(pushed(&cpu).0, 0x0202);
+        
+        //SEI: the IRQ polled before it is still taken, I is pushed set
+        let mut cpu = boot(&[0x58, 0x78, 0xEA]);
+        runInstruction(&mut cpu);
+        cpu.setIRQLine(true);
+        runInstruction(&mut cpu);
+        runInstruction(&mut cpu);
+        assert_eq!(cpu.pc, 0x0400);
+        assert_eq!(pushed(&cpu), (0x0202, 0x24));
+        
+        //PLP clearing I: LDA #$00, PHA, PLP, NOP
+        let mut cpu = boot(&[0xA9, 0x00, 0x48, 0x28, 0xEA, 0xEA]);
+        cpu.setIRQLine(true);
+        for _ in 0..3{
+            runInstruction(&mut cpu);
+        }
+        assert_eq!(cpu.pc, 0x0204);
+        runInstruction(&mut cpu);
+        runInstruction(&mut cpu);
+        assert_eq!(cpu.pc, 0x0400);
+        assert_eq!(pushed(&cpu).0, 0x0205);
+    }
+
+    #[test]
+    fn nmiHijacksBrk(){
+        let mut cpu = boot(&[0x00, 0xFF]);
+        cpu.bus.memory[0x0300] = 0xEA;
+        //NMI during the pushes: the rest of BRK goes to the NMI vector, B stays set
+        cpu.tick().unwrap();
+        cpu.tick().unwrap();
+        cpu.triggerNMI();
+        while !cpu.atInstructionBoundary(){
+            cpu.tick().unwrap();
+        }
+        assert_eq!(cpu.pc, 0x0300);
+        assert_eq!(pushed(&cpu), (0x0202, 0x34));
         
-        inst(self);
+        //it was serviced, the handler runs
+        runInstruction(&mut cpu);
+        assert_eq!(cpu.pc, 0x0301);
     }
-    
-    fn ZeroPageIndexedYWrite(&mut self, inst: fn(&mut Self)->()){
-        self.cycles = 4;
-        inst(self);
+
+    //runs the first count instructions of program
+    fn run(program: &[u8], count: usize) -> CPU6502<TestBus>{
+        let mut cpu = boot(program);
+        for _ in 0..count{
+            runInstruction(&mut cpu);
+        }
+        cpu
+    }
+
+    #[test]
+    fn haltsOnJam(){
+        let mut cpu = boot(&[0x02]);
+        assert!(cpu.tick().is_err());
+        assert!(cpu.isHalted());
+        cpu.triggerRES();
+        runInstruction(&mut cpu);
+        assert!(!cpu.isHalted());
+    }
+
+    #[test]
+    fn arr(){
+        //binary: C is bit 6 of the result, V is bit 6 xor bit 5
+        let cpu = run(&[0x18, 0xA9, 0xC0, 0x6B, 0xFF], 3);
+        assert_eq!((cpu.acc, cpu.status.C, cpu.status.V), (0x60, 1, 0));
+        let cpu = run(&[0x38, 0xA9, 0x40, 0x6B, 0xFF], 3);
+        assert_eq!((cpu.acc, cpu.status.C, cpu.status.V, cpu.status.N), (0xA0, 0, 1, 1));
+        
+        //decimal: the BCD fixup on each nibble of the rotated value, N and Z from before it
+        let cpu = run(&[0xF8, 0x18, 0xA9, 0xFF, 0x6B, 0xFF], 4);
+        assert_eq!((cpu.acc, cpu.status.C, cpu.status.V, cpu.status.N, cpu.status.Z), (0xD5, 1, 0, 0, 0));
+        let cpu = run(&[0xF8, 0x18, 0xA9, 0x40, 0x6B, 0xFF], 4);
+        assert_eq!((cpu.acc, cpu.status.C, cpu.status.V), (0x20, 0, 1));
+    }
+
+    #[test]
+    fn axs(){
+        //X = (A & X) - operand without borrow in, C like CMP
+        let cpu = run(&[0xA9, 0xFF, 0xA2, 0x0F, 0xCB, 0x05], 3);
+        assert_eq!((cpu.x, cpu.status.C, cpu.status.N), (0x0A, 1, 0));
+        let cpu = run(&[0xA9, 0xFF, 0xA2, 0x0F, 0xCB, 0x10], 3);
+        assert_eq!((cpu.x, cpu.status.C, cpu.status.N), (0xFF, 0, 1));
+    }
+
+    //the modified value is written back, the accumulator gets the second operation's result
+    #[test]
+    fn rmwCombinationsWriteBack(){
+        //ISC $10: INC then SBC
+        let mut cpu = boot(&[0x38, 0xA9, 0x20, 0xE7, 0x10]);
+        cpu.bus.memory[0x10] = 0x0F;
+        for _ in 0..3{
+            runInstruction(&mut cpu);
+        }
+        assert_eq!((cpu.bus.memory[0x10], cpu.acc, cpu.status.C), (0x10, 0x10, 1));
         
-        let mut offset = self.pcRead();
-        self.adrMode = offset.to_string()+",Y";
-        offset+=self.y;
+        //RRA $10: ROR then ADC with the carry out of the rotate
+        let mut cpu = boot(&[0x38, 0xA9, 0x10, 0x67, 0x10]);
+        cpu.bus.memory[0x10] = 0x03;
+        for _ in 0..3{
+            runInstruction(&mut cpu);
+        }
+        assert_eq!((cpu.bus.memory[0x10], cpu.acc, cpu.status.C, cpu.status.N), (0x81, 0x92, 0, 1));
         
-        self.bus.write(to16(0,offset), self.buffer);
+        //DCP $10: DEC then CMP
+        let mut cpu = boot(&[0xA9, 0x10, 0xC7, 0x10]);
+        cpu.bus.memory[0x10] = 0x11;
+        for _ in 0..2{
+            runInstruction(&mut cpu);
+        }
+        assert_eq!((cpu.bus.memory[0x10], cpu.acc, cpu.status.C, cpu.status.Z), (0x10, 0x10, 1, 1));
+    }
+
+    #[test]
+    fn sbcEB(){
+        //$EB is SBC #imm
+        for opcode in [0xE9, 0xEB]{
+            let cpu = run(&[0x38, 0xA9, 0x50, opcode, 0xB0], 3);
+            assert_eq!((cpu.acc, cpu.status.C, cpu.status.V, cpu.status.N), (0xA0, 0, 1, 1), "{opcode:02X}");
+        }
+    }
+
+    //the bus cycles of the last instruction of program
+    fn busCycles(program: &[u8], count: usize) -> Vec<Cycle>{
+        let mut cpu = run(program, count - 1);
+        cpu.bus.memory[0x0310] = 0x41;
+        cpu.bus.cycles.clear();
+        runInstruction(&mut cpu);
+        cpu.bus.cycles
+    }
+
+    #[test]
+    fn indexedBusCycles(){
+        use Cycle::*;
+        //LDX #$20, LDA $02F0,X: the dummy read at the uncorrected $0210, then the read at $0310
+        assert_eq!(busCycles(&[0xA2, 0x20, 0xBD, 0xF0, 0x02], 2), [Read(0x0202), Read(0x0203), Read(0x0204), Read(0x0210), Read(0x0310)]);
+        //without a page cross the first read is the right one
+        assert_eq!(busCycles(&[0xA2, 0x20, 0xBD, 0x00, 0x02], 2), [Read(0x0202), Read(0x0203), Read(0x0204), Read(0x0220)]);
+        
+        //LDA #$77, LDX #$20, STA $02F0,X: stores always do the dummy read
+        assert_eq!(busCycles(&[0xA9, 0x77, 0xA2, 0x20, 0x9D, 0xF0, 0x02], 3),
+            [Read(0x0204), Read(0x0205), Read(0x0206), Read(0x0210), Write(0x0310, 0x77)]);
+        assert_eq!(busCycles(&[0xA9, 0x77, 0xA2, 0x20, 0x9D, 0x00, 0x02], 3),
+            [Read(0x0204), Read(0x0205), Read(0x0206), Read(0x0220), Write(0x0220, 0x77)]);
+    }
+
+    #[test]
+    fn rmwWritesTwice(){
+        use Cycle::*;
+        //INC $0310: the old value goes back out before the new one
+        assert_eq!(busCycles(&[0xEE, 0x10, 0x03], 1),
+            [Read(0x0200), Read(0x0201), Read(0x0202), Read(0x0310), Write(0x0310, 0x41), Write(0x0310, 0x42)]);
+        //LDX #$20, INC $02F0,X: and after the dummy read of the indexed modes
+        assert_eq!(busCycles(&[0xA2, 0x20, 0xFE, 0xF0, 0x02], 2),
+            [Read(0x0202), Read(0x0203), Read(0x0204), Read(0x0210), Read(0x0310), Write(0x0310, 0x41), Write(0x0310, 0x42)]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn decimalMode(){
+        //SED, CLC, LDA #$99, ADC #$01: N and V come from the sum before the high nibble fixup, Z from the binary sum
+        let cpu = run(&[0xF8, 0x18, 0xA9, 0x99, 0x69, 0x01], 4);
+        assert_eq!((cpu.acc, cpu.status.C, cpu.status.N, cpu.status.Z), (0x00, 1, 1, 0));
+        
+        //SED, SEC, LDA #$00, SBC #$01: flags from the binary subtraction
+        let cpu = run(&[0xF8, 0x38, 0xA9, 0x00, 0xE9, 0x01], 4);
+        assert_eq!((cpu.acc, cpu.status.C, cpu.status.N, cpu.status.Z), (0x99, 0, 1, 0));
+        
+        //the 2A03 keeps D but adds in binary
+        let mut cpu = boot(&[0xF8, 0x18, 0xA9, 0x99, 0x69, 0x01, 0x38, 0xA9, 0x00, 0xE9, 0x01]);
+        cpu.setVariant(CpuVariant::Ricoh2A03);
+        for _ in 0..4{
+            runInstruction(&mut cpu);
+        }
+        assert_eq!((cpu.acc, cpu.status.C, cpu.status.D), (0x9A, 0, 1));
+        for _ in 0..3{
+            runInstruction(&mut cpu);
+        }
+        assert_eq!((cpu.acc, cpu.status.C), (0xFF, 0));
+    }
+
+    #[test]
+    fn selfLoop(){
+        //NOP, JMP $0201
+        let mut cpu = boot(&[0xEA, 0x4C, 0x01, 0x02]);
+        assert!(!cpu.inSelfLoop());
+        runInstruction(&mut cpu);
+        assert!(!cpu.inSelfLoop());
+        runInstruction(&mut cpu);
+        assert!(cpu.inSelfLoop());
+        assert_eq!(cpu.pc(), 0x0201);
+        
+        //BNE *, taken then not taken
+        let mut cpu = run(&[0xA9, 0x01, 0xD0, 0xFE], 2);
+        assert!(cpu.inSelfLoop());
+        cpu.status.Z = 1;
+        runInstruction(&mut cpu);
+        assert!(!cpu.inSelfLoop());
+        
+        //not in the middle of an instruction
+        cpu.tick().unwrap();
+        assert!(!cpu.inSelfLoop());
+    }
+}
@@ -0,0 +1,462 @@
+use crate::Header_NES::*;
+use crate::Mappers::*;
+
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const TRAINER_SIZE: usize = 512;
+const PRG_RAM_SIZE: usize = 0x2000;
+const CHR_RAM_SIZE: usize = 0x2000;
+const RAW_ADDRESS_SPACE: usize = 0x10000;
+const FOUR_SCREEN_VRAM_SIZE: usize = 0x1000;
+const AUTOSAVE_CHECK_CYCLES: u32 = 0x10000; //how often the autosave timer is looked at
+
+#[derive(Debug)]
+pub enum CartridgeError{
+    Io(std::io::Error),
+    InvalidHeader,
+    RomTooLarge,
+    Truncated{expected: usize, found: usize},
+    NoPrgROM,
+    BinaryTooLarge{size: usize, loadAddress: u16},
+    UnsupportedMapper(u16),
+}
+
+impl fmt::Display for CartridgeError{
+    fn fmt(&self, f:&mut fmt::Formatter<'_>)->fmt::Result{
+        match self{
+            CartridgeError::Io(err) => write!(f, "can't read ROM file: {err}"),
+            CartridgeError::InvalidHeader => write!(f, "not an iNES file (bad magic number)"),
+            CartridgeError::RomTooLarge => write!(f, "ROM size in header is too large"),
+            CartridgeError::Truncated{expected, found} => write!(f, "ROM file is truncated: expected {expected} bytes, found {found}"),
+            CartridgeError::NoPrgROM => write!(f, "ROM has no PRG ROM"),
+            CartridgeError::BinaryTooLarge{size, loadAddress} => write!(f, "binary of {size} bytes doesn't fit in memory when loaded at {loadAddress:#06x}"),
+            CartridgeError::UnsupportedMapper(mapper) => write!(f, "mapper {mapper} is not supported"),
+        }
+    }
+}
+
+impl std::error::Error for CartridgeError{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)>{
+        match self{
+            CartridgeError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for CartridgeError{
+    fn from(err: std::io::Error) -> Self{
+        CartridgeError::Io(err)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RawBinaryOptions{
+    pub loadAddress: u16,
+    pub startPC: Option<u16>, //written to the reset vector, None keeps the vector found in the binary
+    pub fillByte: u8,         //value of the memory not covered by the binary
+}
+
+pub struct Cartridge{
+    mapper: Box<dyn Mapper>,
+    rawBinary: bool,
+    busConflicts: bool,
+    pub header: Header,
+    
+    savePath: Option<PathBuf>,
+    saveDirty: bool,
+    autosaveInterval: Option<Duration>,
+    autosaveCounter: u32,
+    lastSave: Instant,
+}
+
+impl Cartridge{
+    pub fn new(path: &str) -> Result<Cartridge, CartridgeError>{
+        let mut file = File::open(path)?;
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+
+        let mut cartridge = Cartridge::fromBytes(&contents)?;
+        if cartridge.hasBattery(){
+            cartridge.setSavePath(Path::new(path).with_extension("sav"))?;
+        }
+        Ok(cartridge)
+    }
+
+    pub fn fromBytes(contents: &[u8]) -> Result<Cartridge, CartridgeError>{
+        let header = Header::parse(contents)?;
+
+        if header.prgROMSize == 0{
+            return Err(CartridgeError::NoPrgROM);
+        }
+
+        let expected = header.romDataSize()?.checked_add(HEADER_SIZE).ok_or(CartridgeError::RomTooLarge)?;
+        if contents.len() < expected{
+            return Err(CartridgeError::Truncated{expected, found: contents.len()});
+        }
+
+        let mut offset = HEADER_SIZE;
+
+        //NES 2.0 headers give the exact size (possibly none), iNES headers always ask for at least 8KB
+        let mut prgRAMSize = header.prgRAMSize + header.prgNVRAMSize;
+        if header.trainer{
+            prgRAMSize = prgRAMSize.max(PRG_RAM_SIZE);
+        }
+        let mut prgRAM = vec![0u8; prgRAMSize];
+        if header.trainer{
+            //the trainer lives at $7000-$71FF
+            prgRAM[0x1000..0x1000+TRAINER_SIZE].copy_from_slice(&contents[offset..offset+TRAINER_SIZE]);
+            offset += TRAINER_SIZE;
+        }
+
+        let prgROM = contents[offset..offset+header.prgROMSize].to_vec();
+        offset += header.prgROMSize;
+
+        let chrIsRAM = header.chrROMSize == 0;
+        let chrROM = if chrIsRAM{
+            vec![0u8; (header.chrRAMSize + header.chrNVRAMSize).max(CHR_RAM_SIZE)]
+        } else {
+            contents[offset..offset+header.chrROMSize].to_vec()
+        };
+
+        let vram = if header.mirroring == Mirroring::FourScreen {vec![0u8; FOUR_SCREEN_VRAM_SIZE]} else {Vec::new()};
+        let data = CartridgeData{prgROM, chr: chrROM, chrIsRAM, prgRAM, vram};
+
+        Ok(Cartridge{
+            mapper: newMapper(&header, data)?,
+            rawBinary: false,
+            busConflicts: header.busConflicts,
+            header,
+            savePath: None,
+            saveDirty: false,
+            autosaveInterval: None,
+            autosaveCounter: 0,
+            lastSave: Instant::now(),
+        })
+    }
+
+    //loads a plain 6502 binary into a flat 64K address space, with no header and no mapper
+    pub fn fromRawBinary(path: &str, options: &RawBinaryOptions) -> Result<Cartridge, CartridgeError>{
+        let mut file = File::open(path)?;
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+
+        Cartridge::rawBinaryFromBytes(&contents, options)
+    }
+
+    pub fn rawBinaryFromBytes(contents: &[u8], options: &RawBinaryOptions) -> Result<Cartridge, CartridgeError>{
+        let loadAddress = options.loadAddress as usize;
+        if loadAddress + contents.len() > RAW_ADDRESS_SPACE{
+            return Err(CartridgeError::BinaryTooLarge{size: contents.len(), loadAddress: options.loadAddress});
+        }
+
+        let mut ram = vec![options.fillByte; RAW_ADDRESS_SPACE];
+        ram[loadAddress..loadAddress+contents.len()].copy_from_slice(contents);
+
+        if let Some(startPC) = options.startPC{
+            ram[0xFFFC] = (startPC & 0xFF) as u8;
+            ram[0xFFFD] = (startPC >> 8) as u8;
+        }
+
+        Ok(Cartridge{
+            mapper: Box::new(Flat::new(ram)),
+            rawBinary: true,
+            busConflicts: false,
+            header: Header::default(),
+            savePath: None,
+            saveDirty: false,
+            autosaveInterval: None,
+            autosaveCounter: 0,
+            lastSave: Instant::now(),
+        })
+    }
+
+    pub fn isRawBinary(&self) -> bool{
+        self.rawBinary
+    }
+    
+    pub fn cpuRead(&mut self, adr: u16) -> u8{
+        self.mapper.cpuRead(adr)
+    }
+    
+    pub fn cpuWrite(&mut self, adr: u16, data: u8){
+        //with bus conflicts the ROM drives the data bus at the same time as the CPU, the board sees the AND of both
+        let data = if self.busConflicts && adr >= 0x8000{
+            data & self.mapper.cpuRead(adr)
+        } else {
+            data
+        };
+        if (0x6000..=0x7FFF).contains(&adr) && self.hasBattery(){
+            self.saveDirty = true;
+        }
+        self.mapper.cpuWrite(adr, data);
+    }
+    
+    //overrides the value given by the header, for ROMs known to need (or break with) bus conflicts
+    pub fn setBusConflicts(&mut self, enabled: bool){
+        self.busConflicts = enabled;
+    }
+    
+    pub fn ppuRead(&mut self, adr: u16, ciram: &[u8]) -> u8{
+        let adr = adr & 0x3FFF;
+        self.mapper.ppuA12(adr & 0x1000 != 0);
+        if adr < 0x2000{
+            self.mapper.ppuRead(adr)
+        }
+        else {
+            self.mapper.nametableRead(adr, ciram)
+        }
+    }
+    
+    pub fn ppuWrite(&mut self, adr: u16, data: u8, ciram: &mut [u8]){
+        let adr = adr & 0x3FFF;
+        self.mapper.ppuA12(adr & 0x1000 != 0);
+        if adr < 0x2000{
+            if self.nvramSizes().1 != 0{
+                self.saveDirty = true;
+            }
+            self.mapper.ppuWrite(adr, data);
+        }
+        else {
+            self.mapper.nametableWrite(adr, data, ciram);
+        }
+    }
+    
+    #[allow(dead_code)]
+    pub fn mirroring(&self) -> Mirroring{
+        self.mapper.mirroring()
+    }
+    
+    pub fn irq(&self) -> bool{
+        self.mapper.irq()
+    }
+    
+    pub fn cpuCycle(&mut self){
+        self.mapper.cpuCycle();
+        
+        if let Some(interval) = self.autosaveInterval{
+            self.autosaveCounter += 1;
+            if self.autosaveCounter == AUTOSAVE_CHECK_CYCLES{
+                self.autosaveCounter = 0;
+                if self.saveDirty && self.lastSave.elapsed() >= interval{
+                    if let Err(err) = self.flushSave(){
+                        eprintln!("Failed to autosave: {err}");
+                    }
+                }
+            }
+        }
+    }
+    
+    pub fn hasBattery(&self) -> bool{
+        let (prgSize, chrSize) = self.nvramSizes();
+        prgSize + chrSize != 0
+    }
+    
+    //battery backed bytes at the end of the PRG RAM and of the CHR RAM
+    fn nvramSizes(&self) -> (usize, usize){
+        if !self.header.battery{
+            return (0, 0);
+        }
+        let data = self.mapper.data();
+        let chrSize = if data.chrIsRAM {self.header.chrNVRAMSize.min(data.chr.len())} else {0};
+        (self.header.prgNVRAMSize.min(data.prgRAM.len()), chrSize)
+    }
+    
+    //content of the .sav file: PRG NVRAM followed by CHR NVRAM
+    pub fn saveData(&self) -> Vec<u8>{
+        let (prgSize, chrSize) = self.nvramSizes();
+        let data = self.mapper.data();
+        let mut save = data.prgRAM[data.prgRAM.len()-prgSize..].to_vec();
+        save.extend_from_slice(&data.chr[data.chr.len()-chrSize..]);
+        save
+    }
+    
+    pub fn loadSaveData(&mut self, save: &[u8]){
+        let (prgSize, chrSize) = self.nvramSizes();
+        let data = self.mapper.dataMut();
+        
+        let prgStart = data.prgRAM.len()-prgSize;
+        let prgPart = &save[..prgSize.min(save.len())];
+        data.prgRAM[prgStart..prgStart+prgPart.len()].copy_from_slice(prgPart);
+        
+        let chrStart = data.chr.len()-chrSize;
+        let chrPart = &save[prgPart.len()..(prgSize+chrSize).min(save.len())];
+        data.chr[chrStart..chrStart+chrPart.len()].copy_from_slice(chrPart);
+    }
+    
+    //loads the save file if it exists and keeps the path to write it back
+    pub fn setSavePath(&mut self, path: PathBuf) -> Result<(), CartridgeError>{
+        match std::fs::read(&path){
+            Ok(save) => self.loadSaveData(&save),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+            Err(err) => return Err(err.into()),
+        }
+        self.savePath = Some(path);
+        self.saveDirty = false;
+        Ok(())
+    }
+    
+    pub fn setAutosaveInterval(&mut self, interval: Option<Duration>){
+        self.autosaveInterval = interval;
+    }
+    
+    //writes the save file if the battery backed RAM changed, through a temporary file so a crash can't leave it half written
+    pub fn flushSave(&mut self) -> std::io::Result<()>{
+        let Some(path) = &self.savePath else {
+            return Ok(());
+        };
+        if !self.saveDirty{
+            return Ok(());
+        }
+        
+        let tmpPath = path.with_extension("sav.tmp");
+        let mut file = File::create(&tmpPath)?;
+        file.write_all(&self.saveData())?;
+        file.sync_all()?;
+        std::fs::rename(&tmpPath, path)?;
+        
+        self.saveDirty = false;
+        self.lastSave = Instant::now();
+        Ok(())
+    }
+}
+
+//a copy (like a snapshot of the Nes) doesn't own the save file, dropping it mustn't overwrite the save with old RAM
+impl Clone for Cartridge{
+    fn clone(&self) -> Self{
+        Cartridge{
+            mapper: self.mapper.clone(),
+            rawBinary: self.rawBinary,
+            busConflicts: self.busConflicts,
+            header: self.header.clone(),
+            savePath: None,
+            saveDirty: false,
+            autosaveInterval: self.autosaveInterval,
+            autosaveCounter: self.autosaveCounter,
+            lastSave: self.lastSave,
+        }
+    }
+}
+
+impl Drop for Cartridge{
+    fn drop(&mut self){
+        if let Err(err) = self.flushSave(){
+            eprintln!("Failed to write save file: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    //NROM-128 with battery backed PRG RAM
+    fn batteryRom() -> Vec<u8>{
+        let mut rom = vec![b'N', b'E', b'S', 0x1A, 1, 1, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        rom.resize(HEADER_SIZE + 0x4000 + 0x2000, 0);
+        rom
+    }
+
+    fn savePath(name: &str) -> PathBuf{
+        let path = std::env::temp_dir().join(format!("nes-{}-{name}.sav", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn saveWrittenOnDrop(){
+        let path = savePath("drop");
+        let mut cart = Cartridge::fromBytes(&batteryRom()).unwrap();
+        cart.setSavePath(path.clone()).unwrap();
+        cart.cpuWrite(0x6000, 0x42);
+        drop(cart);
+        
+        let save = std::fs::read(&path).unwrap();
+        assert_eq!(save.len(), PRG_RAM_SIZE);
+        assert_eq!(save[0], 0x42);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn cloneDoesntOwnSave(){
+        let path = savePath("clone");
+        let mut cart = Cartridge::fromBytes(&batteryRom()).unwrap();
+        cart.setSavePath(path.clone()).unwrap();
+        cart.cpuWrite(0x6000, 0x01);
+        
+        let mut snapshot = cart.clone();
+        snapshot.cpuWrite(0x6000, 0x02);
+        drop(snapshot);
+        assert!(!path.exists());
+        
+        drop(cart);
+        assert_eq!(std::fs::read(&path).unwrap()[0], 0x01);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    //NES 2.0 UxROM with 8 PRG banks filled with their number, except $C000 in the fixed bank which holds $05
+    fn uxrom(submapper: u8) -> Cartridge{
+        let mut rom = vec![b'N', b'E', b'S', 0x1A, 8, 0, 0x20, 0x08, submapper << 4, 0, 0, 0x07, 0, 0, 0, 0];
+        for bank in 0..8{
+            rom.extend(vec![bank as u8; 0x4000]);
+        }
+        rom[HEADER_SIZE + 7*0x4000] = 0x05;
+        Cartridge::fromBytes(&rom).unwrap()
+    }
+
+    #[test]
+    fn busConflicts(){
+        //submapper 2 has them: the latch gets $06 & $05
+        let mut cart = uxrom(2);
+        cart.cpuWrite(0xC000, 0x06);
+        assert_eq!(cart.cpuRead(0x8000), 4);
+        
+        //submapper 1 doesn't
+        let mut cart = uxrom(1);
+        cart.cpuWrite(0xC000, 0x06);
+        assert_eq!(cart.cpuRead(0x8000), 6);
+    }
+
+    #[test]
+    fn busConflictsOverride(){
+        let mut cart = uxrom(2);
+        cart.setBusConflicts(false);
+        cart.cpuWrite(0xC000, 0x06);
+        assert_eq!(cart.cpuRead(0x8000), 6);
+        
+        let mut cart = uxrom(1);
+        cart.setBusConflicts(true);
+        cart.cpuWrite(0xC000, 0x06);
+        assert_eq!(cart.cpuRead(0x8000), 4);
+    }
+
+    #[test]
+    fn rawBinary(){
+        let options = RawBinaryOptions{loadAddress: 0x0400, startPC: Some(0x1234), fillByte: 0xAA};
+        let mut cart = Cartridge::rawBinaryFromBytes(&[1, 2, 3], &options).unwrap();
+        assert!(cart.isRawBinary());
+        assert_eq!([0x03FF, 0x0400, 0x0401, 0x0402, 0x0403].map(|adr| cart.cpuRead(adr)), [0xAA, 1, 2, 3, 0xAA]);
+        assert_eq!([cart.cpuRead(0xFFFC), cart.cpuRead(0xFFFD)], [0x34, 0x12]);
+        //it's all RAM
+        cart.cpuWrite(0x8000, 0x55);
+        assert_eq!(cart.cpuRead(0x8000), 0x55);
+        
+        //without a start address the binary's own reset vector is kept
+        let mut binary = vec![0; RAW_ADDRESS_SPACE];
+        binary[0xFFFC..0xFFFE].copy_from_slice(&[0x00, 0x80]);
+        let mut cart = Cartridge::rawBinaryFromBytes(&binary, &RawBinaryOptions::default()).unwrap();
+        assert_eq!([cart.cpuRead(0xFFFC), cart.cpuRead(0xFFFD)], [0x00, 0x80]);
+    }
+
+    #[test]
+    fn rawBinaryTooLarge(){
+        let options = RawBinaryOptions{loadAddress: 0xFF00, ..Default::default()};
+        assert!(Cartridge::rawBinaryFromBytes(&[0; 0x100], &options).is_ok());
+        assert!(matches!(Cartridge::rawBinaryFromBytes(&[0; 0x101], &options), Err(CartridgeError::BinaryTooLarge{size: 0x101, loadAddress: 0xFF00})));
+        assert!(matches!(Cartridge::rawBinaryFromBytes(&[0; RAW_ADDRESS_SPACE + 1], &RawBinaryOptions::default()), Err(CartridgeError::BinaryTooLarge{..})));
+    }
+}
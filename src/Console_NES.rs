@@ -0,0 +1,493 @@
+use crate::CPU::*;
+use crate::Bus_NES::Bus;
+use crate::Cartridge_NES::Cartridge;
+use crate::PPU_NES::Renderer;
+use crate::Palette_NES::Palette;
+
+//NTSC: the CPU divides the 21.477272MHz master clock by 12 and the PPU by 4, 3 dots per CPU cycle
+const CPU_DIVIDER: u64 = 12;
+const PPU_DIVIDER: u64 = 4;
+
+//the whole console, every component runs off the master clock
+#[derive(Clone)]
+pub struct Nes{
+    cpu: CPU6502<Bus>,
+    masterClock: u64,
+    palette: Palette,
+}
+
+impl Nes{
+    pub fn new(cart: Cartridge) -> Self{
+        let mut cpu = CPU6502::new(Bus::new(cart));
+        cpu.setVariant(CpuVariant::Ricoh2A03);
+        Nes{
+            cpu,
+            masterClock: 0,
+            palette: Palette::default(),
+        }
+    }
+    
+    pub fn cpu(&self) -> &CPU6502<Bus>{
+        &self.cpu
+    }
+    
+    pub fn cpuMut(&mut self) -> &mut CPU6502<Bus>{
+        &mut self.cpu
+    }
+    
+    pub fn bus(&self) -> &Bus{
+        self.cpu.bus()
+    }
+    
+    pub fn busMut(&mut self) -> &mut Bus{
+        self.cpu.busMut()
+    }
+    
+    //master clock ticks since power on
+    #[allow(dead_code)]
+    pub fn masterClock(&self) -> u64{
+        self.masterClock
+    }
+    
+    //one CPU cycle and the PPU dots and APU cycle that go with it
+    pub fn stepCycle(&mut self) -> Result<(), CpuError>{
+        let mut result = Ok(());
+        for _ in 0..CPU_DIVIDER{
+            if self.masterClock.is_multiple_of(CPU_DIVIDER){
+                result = self.cpu.tick();
+                self.cpu.busMut().apu.tick();
+            }
+            if self.masterClock.is_multiple_of(PPU_DIVIDER){
+                let bus = self.cpu.busMut();
+                bus.ppu.tick(&mut bus.cart);
+            }
+            self.masterClock += 1;
+        }
+        result
+    }
+    
+    //runs until the CPU is about to start the next instruction (or interupt sequence)
+    pub fn stepInstruction(&mut self) -> Result<(), CpuError>{
+        self.stepCycle()?;
+        while !self.cpu.atInstructionBoundary(){
+            self.stepCycle()?;
+        }
+        Ok(())
+    }
+    
+    //runs until the PPU starts the next frame, the audio of the previous frame is dropped first
+    pub fn stepFrame(&mut self) -> Result<(), CpuError>{
+        self.busMut().apu.clearSamples();
+        let frame = self.bus().ppu.frame();
+        while self.bus().ppu.frame() == frame{
+            self.stepCycle()?;
+        }
+        Ok(())
+    }
+    
+    //the reset button: RAM, the cartridge and most of the PPU survive
+    pub fn reset(&mut self){
+        self.cpu.triggerRES();
+        self.busMut().reset();
+    }
+    
+    #[allow(dead_code)]
+    pub fn powerCycle(&mut self){
+        self.cpu.powerCycle();
+        self.busMut().powerCycle();
+        self.masterClock = 0;
+    }
+    
+    //can be switched at any time, the new renderer takes over on the next dot
+    pub fn setRenderer(&mut self, renderer: Renderer){
+        self.busMut().ppu.setRenderer(renderer);
+    }
+    
+    //256x240 palette indices, bits 6-8 hold the emphasis bits of PPUMASK
+    pub fn framebuffer(&self) -> &[u16]{
+        self.bus().ppu.framebuffer()
+    }
+    
+    pub fn setPalette(&mut self, palette: Palette){
+        self.palette = palette;
+    }
+    
+    #[allow(dead_code)]
+    pub fn palette(&self) -> &Palette{
+        &self.palette
+    }
+    
+    //the framebuffer as RGB24 through the current palette
+    #[allow(dead_code)]
+    pub fn frameRGB(&self) -> Vec<u8>{
+        self.palette.convert(self.framebuffer())
+    }
+    
+    //mono samples at APU_NES::SAMPLE_RATE, since the start of the last stepFrame
+    #[allow(dead_code)]
+    pub fn audioSamples(&self) -> &[f32]{
+        self.bus().apu.samples()
+    }
+    
+    pub fn clearAudioSamples(&mut self){
+        self.busMut().apu.clearSamples();
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    //$C000: JMP $C000, the NMI handler at $C003 is an RTI
+    fn program(bankSize: usize) -> Vec<u8>{
+        let mut prg = vec![0xEA; bankSize];
+        prg[..4].copy_from_slice(&[0x4C, 0x00, 0xC0, 0x40]);
+        prg[bankSize-6..bankSize-2].copy_from_slice(&[0x03, 0xC0, 0x00, 0xC0]);
+        prg
+    }
+
+    //NROM-128 with CHR RAM, or MMC3 with 32KB PRG (the last 8KB bank is the one at $E000)
+    fn cart(mapper: u8) -> Cartridge{
+        let mut rom = vec![b'N', b'E', b'S', 0x1A, 1, 0, mapper << 4, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        if mapper == 4{
+            rom[4] = 2;
+            rom.extend(vec![0xEA; 0x6000]);
+            let mut prg = program(0x2000);
+            prg[2] = 0xE0;
+            prg[0x1FFB] = 0xE0;
+            prg[0x1FFD] = 0xE0;
+            rom.extend(prg);
+        }
+        else {
+            rom.extend(program(0x4000));
+        }
+        Cartridge::fromBytes(&rom).unwrap()
+    }
+
+    //NROM-128 starting at $C010 with code
+    fn cartRunning(code: &[u8]) -> Cartridge{
+        let mut rom = vec![b'N', b'E', b'S', 0x1A, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let mut prg = program(0x4000);
+        prg[0x10..0x10 + code.len()].copy_from_slice(code);
+        prg[0x3FFC] = 0x10;
+        rom.extend(prg);
+        Cartridge::fromBytes(&rom).unwrap()
+    }
+
+    fn ppuWrite(nes: &mut Nes, adr: u16, data: u8){
+        let bus = nes.busMut();
+        bus.write(0x2006, (adr >> 8) as u8);
+        bus.write(0x2006, adr as u8);
+        bus.write(0x2007, data);
+    }
+
+    //background tiles in rows 1, 2 and 6 (row 2 starts on the leftmost column), sprite 0 over row 6
+    //and a few more sprites, with fine X scroll
+    fn scene(mapper: u8) -> Nes{
+        let mut nes = Nes::new(cart(mapper));
+        for i in 0..8{
+            ppuWrite(&mut nes, 0x0010 + i, 0xFF);
+            ppuWrite(&mut nes, 0x0028 + i, 0x81);
+            ppuWrite(&mut nes, 0x1010 + i, 0x3C);
+        }
+        ppuWrite(&mut nes, 0x2021, 1);
+        ppuWrite(&mut nes, 0x2040, 2);
+        for column in 0..32{
+            ppuWrite(&mut nes, 0x20C0 + column, 1);
+        }
+        ppuWrite(&mut nes, 0x23C0, 0x01);
+        for (i, color) in [0x0F, 0x11, 0x21, 0x13, 0x0F, 0x21, 0x22, 0x23].iter().enumerate(){
+            ppuWrite(&mut nes, 0x3F00 + i as u16, *color);
+        }
+        ppuWrite(&mut nes, 0x3F11, 0x31);
+        
+        let bus = nes.busMut();
+        bus.write(0x2003, 0);
+        for i in 0..64u8{
+            let sprite = match i{
+                0 => [49, 1, 0, 100],
+                1..=3 => [20 + i*30, 1, i & 3, i*50],
+                _ => [0xF0, 0, 0, 0],
+            };
+            for data in sprite{
+                bus.write(0x2004, data);
+            }
+        }
+        bus.write(0x2000, 0x08);
+        bus.write(0x2005, 3);
+        bus.write(0x2005, 0);
+        bus.write(0x2001, 0x1E);
+        nes
+    }
+
+    fn runToScanline(nes: &mut Nes, scanline: u16){
+        while nes.bus().ppu.scanline() != scanline{
+            nes.stepCycle().unwrap();
+        }
+    }
+
+    #[test]
+    fn powerCycleKeepsRenderer(){
+        let mut nes = Nes::new(cart(0));
+        nes.setRenderer(Renderer::Scanline);
+        nes.powerCycle();
+        assert_eq!(nes.bus().ppu.renderer(), Renderer::Scanline);
+    }
+
+    #[test]
+    fn renderersDrawTheSameFrame(){
+        let mut dot = scene(0);
+        let mut scanline = dot.clone();
+        scanline.setRenderer(Renderer::Scanline);
+        for _ in 0..3{
+            dot.stepFrame().unwrap();
+            scanline.stepFrame().unwrap();
+        }
+        assert_eq!(dot.framebuffer()[16*256 + 4], 0x21); //leftmost tile of row 2, scrolled by 3
+        assert_eq!(dot.framebuffer(), scanline.framebuffer());
+    }
+
+    #[test]
+    fn renderersFindSpriteZeroHitOnTheSameLine(){
+        for renderer in [Renderer::Dot, Renderer::Scanline]{
+            let mut nes = scene(0);
+            nes.setRenderer(renderer);
+            nes.stepFrame().unwrap();
+            nes.stepFrame().unwrap();
+            runToScanline(&mut nes, 50);
+            assert_eq!(nes.busMut().read(0x2002) & 0x40, 0, "{renderer:?}");
+            runToScanline(&mut nes, 51);
+            assert_eq!(nes.busMut().read(0x2002) & 0x40, 0x40, "{renderer:?}");
+        }
+    }
+
+    #[test]
+    fn renderersClockMMC3OnTheSameLine(){
+        let mut lines = Vec::new();
+        for renderer in [Renderer::Dot, Renderer::Scanline]{
+            let mut nes = scene(4);
+            nes.setRenderer(renderer);
+            nes.stepFrame().unwrap();
+            let bus = nes.busMut();
+            bus.write(0xC000, 16);
+            bus.write(0xC001, 0);
+            bus.write(0xE001, 0);
+            while !nes.bus().cart.irq(){
+                nes.stepCycle().unwrap();
+            }
+            lines.push(nes.bus().ppu.scanline());
+        }
+        assert_eq!(lines, [16, 16]);
+    }
+
+    #[test]
+    fn stepInstructionStopsOnBoundary(){
+        let mut nes = Nes::new(cart(0));
+        nes.stepInstruction().unwrap();
+        assert!(nes.cpu().atInstructionBoundary());
+        assert!(nes.cpuMut().trace().starts_with("C000"));
+        assert_eq!(nes.masterClock() % CPU_DIVIDER, 0);
+        
+        //JMP takes 3 cycles
+        let clock = nes.masterClock();
+        nes.stepInstruction().unwrap();
+        assert_eq!(nes.masterClock() - clock, 3*CPU_DIVIDER);
+    }
+
+    #[test]
+    fn stepFrame(){
+        let mut nes = Nes::new(cart(0));
+        nes.stepFrame().unwrap();
+        let frame = nes.bus().ppu.frame();
+        let clock = nes.masterClock();
+        nes.stepFrame().unwrap();
+        assert_eq!(nes.bus().ppu.frame(), frame + 1);
+        //341x262 dots, give or take the CPU cycle the frame ends in
+        assert!((nes.masterClock() - clock).abs_diff(341*262*PPU_DIVIDER) < CPU_DIVIDER);
+        //44100/60
+        assert!((734..=736).contains(&nes.audioSamples().len()));
+        nes.clearAudioSamples();
+        assert!(nes.audioSamples().is_empty());
+    }
+
+    #[test]
+    fn resetKeepsRAM(){
+        let mut nes = Nes::new(cart(0));
+        nes.stepFrame().unwrap();
+        nes.busMut().write(0x0000, 0x55);
+        nes.busMut().write(0x2000, 0x80);
+        nes.reset();
+        nes.stepInstruction().unwrap();
+        assert!(nes.cpuMut().trace().starts_with("C000"));
+        assert_eq!(nes.busMut().read(0x0000), 0x55);
+        assert!(!nes.bus().ppu.nmi());
+    }
+
+    #[test]
+    fn powerCycleClearsRAM(){
+        let mut nes = Nes::new(cart(0));
+        nes.stepFrame().unwrap();
+        nes.busMut().write(0x0000, 0x55);
+        nes.powerCycle();
+        assert_eq!(nes.masterClock(), 0);
+        assert_eq!(nes.bus().cycle(), 0);
+        assert_eq!(nes.bus().ppu.frame(), 0);
+        assert_eq!(nes.busMut().read(0x0000), 0);
+        nes.stepInstruction().unwrap();
+        assert!(nes.cpuMut().trace().starts_with("C000"));
+    }
+
+    #[test]
+    fn frameRGB(){
+        let mut nes = Nes::new(cart(0));
+        //with rendering off the backdrop is drawn, v is moved out of the palette so it isn't shown instead
+        ppuWrite(&mut nes, 0x3F00, 0x16);
+        ppuWrite(&mut nes, 0x2000, 0);
+        nes.stepFrame().unwrap();
+        nes.stepFrame().unwrap();
+        
+        let rgb = nes.frameRGB();
+        assert_eq!(rgb.len(), 256*240*3);
+        assert_eq!(rgb[..3], nes.palette().rgb(0x16));
+        assert_eq!(rgb[..3], [152, 34, 32]);
+        
+        //grayscale and red emphasis
+        nes.busMut().write(0x2001, 0x21);
+        nes.stepFrame().unwrap();
+        nes.stepFrame().unwrap();
+        assert_eq!(nes.framebuffer()[1000], 0x10 | 0x40);
+        let bus = nes.busMut();
+        bus.write(0x2006, 0x3F);
+        bus.write(0x2006, 0x00);
+        assert_eq!(bus.read(0x2007) & 0x3F, 0x10);
+        
+        nes.setPalette(Palette::fromBytes(&[1, 2, 3].repeat(512)).unwrap());
+        assert_eq!(nes.frameRGB()[..3], [1, 2, 3]);
+    }
+
+    //stops right after the CPU cycle that takes the PPU into line 241 on the given dot, the phase moves by 2 dots each frame
+    fn runToVblankDot(nes: &mut Nes, dot: u16){
+        loop{
+            runToScanline(nes, 241);
+            if nes.bus().ppu.dot() == dot{
+                return;
+            }
+            runToScanline(nes, 0);
+        }
+    }
+
+    //runs instructions until the CPU enters the NMI handler or the PPU reaches scanline
+    fn runToNMI(nes: &mut Nes, scanline: u16) -> bool{
+        while nes.bus().ppu.scanline() != scanline{
+            nes.stepInstruction().unwrap();
+            if nes.cpuMut().trace().starts_with("C003"){
+                return true;
+            }
+        }
+        false
+    }
+
+    #[test]
+    fn vblankStartsOnDot1(){
+        let mut nes = Nes::new(cart(0));
+        nes.busMut().write(0x2000, 0x80);
+        runToVblankDot(&mut nes, 1);
+        assert!(!nes.bus().ppu.nmi());
+        nes.stepCycle().unwrap();
+        assert!(nes.bus().ppu.nmi());
+        
+        runToScanline(&mut nes, 0);
+        runToVblankDot(&mut nes, 0);
+        assert!(!nes.bus().ppu.nmi());
+        nes.stepCycle().unwrap();
+        assert!(nes.bus().ppu.nmi());
+        assert_eq!(nes.busMut().read(0x2002) & 0x80, 0x80);
+    }
+
+    //the NMI output is PPUCTRL bit 7 and the vblank flag, the CPU takes it on the rising edge
+    #[test]
+    fn nmiIsCtrlAndVblank(){
+        let mut nes = Nes::new(cart(0));
+        runToScanline(&mut nes, 242);
+        assert!(!nes.bus().ppu.nmi());
+        nes.busMut().write(0x2000, 0x80);
+        assert!(nes.bus().ppu.nmi());
+        assert!(runToNMI(&mut nes, 261));
+        
+        //no new edge while both stay set
+        assert!(!runToNMI(&mut nes, 261));
+        runToScanline(&mut nes, 0);
+        assert!(!nes.bus().ppu.nmi());
+        
+        //the next frame's vblank
+        assert!(runToNMI(&mut nes, 242));
+        assert_eq!(nes.bus().ppu.scanline(), 241);
+        //at most the rest of one JMP, another one and the 7 cycle sequence after dot 1
+        assert!(nes.bus().ppu.dot() <= 1 + 13*3);
+    }
+
+    #[test]
+    fn togglingCtrlInVblankRetriggersNMI(){
+        let mut nes = Nes::new(cart(0));
+        nes.busMut().write(0x2000, 0x80);
+        assert!(runToNMI(&mut nes, 242));
+        //the CPU has to see the line low for a cycle
+        nes.busMut().write(0x2000, 0x00);
+        assert!(!nes.bus().ppu.nmi());
+        nes.stepCycle().unwrap();
+        nes.busMut().write(0x2000, 0x80);
+        assert!(runToNMI(&mut nes, 261));
+    }
+
+    //reading $2002 on the dot before the flag is set returns it clear, and it isn't set for that frame
+    #[test]
+    fn statusReadSuppressesVblank(){
+        let mut nes = Nes::new(cart(0));
+        nes.busMut().write(0x2000, 0x80);
+        runToVblankDot(&mut nes, 1);
+        assert_eq!(nes.busMut().read(0x2002) & 0x80, 0);
+        nes.stepCycle().unwrap();
+        assert!(!nes.bus().ppu.nmi());
+        assert!(!runToNMI(&mut nes, 261));
+        assert_eq!(nes.busMut().read(0x2002) & 0x80, 0);
+        
+        //the next frame is back to normal
+        assert!(runToNMI(&mut nes, 242));
+    }
+
+    //STA $4014 takes its 4 cycles, then the CPU halts for 513 cycles, or 514 when the first read would land on a put cycle
+    #[test]
+    fn oamDMA(){
+        let mut lengths = Vec::new();
+        //LDA #$08 or LDA $00 (2 or 3 cycles), STA $4014, JMP $C015
+        for load in [[0xA9, 0x08], [0xA5, 0x00]]{
+            let mut nes = Nes::new(cartRunning(&[load[0], load[1], 0x8D, 0x14, 0x40, 0x4C, 0x15, 0xC0]));
+            //page $08 is a mirror of page $00
+            for i in 0..=0xFF{
+                nes.busMut().write(i, (i as u8).wrapping_add(0x08));
+            }
+            //the reset sequence, then the load
+            nes.stepInstruction().unwrap();
+            nes.stepInstruction().unwrap();
+            
+            //STA takes cycles +1 to +4, the DMA halts on +5 and its first read wants an even cycle
+            let cycle = nes.bus().cycle();
+            let clock = nes.masterClock();
+            nes.stepInstruction().unwrap();
+            let expected = if cycle.is_multiple_of(2) {4 + 513} else {4 + 514};
+            assert_eq!((nes.masterClock() - clock) / CPU_DIVIDER, expected);
+            assert!(nes.cpuMut().trace().starts_with("C015"));
+            lengths.push(expected);
+            
+            let bus = nes.busMut();
+            for i in 0..=0xFF{
+                bus.write(0x2003, i);
+                let data = i.wrapping_add(0x08);
+                //attribute bits 2-4 don't exist
+                assert_eq!(bus.read(0x2004), if i & 3 == 2 {data & 0xE3} else {data}, "OAM {i:02X}");
+            }
+        }
+        lengths.sort();
+        assert_eq!(lengths, [4 + 513, 4 + 514]);
+    }
+}
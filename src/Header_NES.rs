@@ -0,0 +1,282 @@
+use crate::BinaryHandler;
+use crate::Cartridge_NES::CartridgeError;
+use crate::Mappers::Mirroring;
+
+use std::fmt;
+
+pub const HEADER_SIZE: usize = 16;
+const PRG_BANK_SIZE: usize = 0x4000;
+const CHR_BANK_SIZE: usize = 0x2000;
+const PRG_RAM_BANK_SIZE: usize = 0x2000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HeaderFormat{
+    INes,
+    Nes2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Timing{
+    NTSC,
+    PAL,
+    MultiRegion,
+    Dendy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConsoleType{
+    NES,
+    VsSystem,
+    Playchoice10,
+    Extended(u8), //byte 13 low nibble (Famiclone with decimal mode, VT01, ...)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header{
+    pub format: HeaderFormat,
+    pub mapper: u16,
+    pub submapper: u8,
+    pub prgROMSize: usize, //all sizes are in bytes
+    pub chrROMSize: usize,
+    pub prgRAMSize: usize,
+    pub prgNVRAMSize: usize,
+    pub chrRAMSize: usize,
+    pub chrNVRAMSize: usize,
+    pub mirroring: Mirroring,
+    pub battery: bool,
+    pub trainer: bool,
+    pub busConflicts: bool,
+    pub timing: Timing,
+    pub console: ConsoleType,
+    pub vsPPUType: u8,      //only meaningful for ConsoleType::VsSystem
+    pub vsHardwareType: u8,
+    pub miscROMs: u8,
+    pub expansionDevice: u8,
+}
+
+impl Header{
+    pub fn parse(header: &[u8]) -> Result<Header, CartridgeError>{
+        if header.len() < HEADER_SIZE{
+            return Err(CartridgeError::Truncated{expected: HEADER_SIZE, found: header.len()});
+        }
+
+        if !(header[0] == b'N' && header[1] == b'E' && header[2] == b'S' && header[3] == 0x1A){
+            return Err(CartridgeError::InvalidHeader);
+        }
+
+        if header[7] & 0x0C == 0x08{
+            Header::parseNes2(header)
+        }
+        else {
+            Ok(Header::parseINes(header))
+        }
+    }
+
+    fn parseINes(header: &[u8]) -> Header{
+        let flags6 = header[6];
+        let mut flags7 = header[7];
+        let flags8 = header[8];
+        let flags9 = header[9];
+        let flags10 = header[10];
+
+        //old dumping tools wrote garbage (like "DiskDude!") in bytes 7-15, the upper mapper nibble can't be trusted then
+        if header[12..16].iter().any(|&b| b != 0){
+            flags7 = 0;
+        }
+
+        let chrROMSize = (header[5] as usize)*CHR_BANK_SIZE;
+
+        Header{
+            format: HeaderFormat::INes,
+            mapper: ((flags6 >> 4) | (flags7 & 0xF0)) as u16,
+            submapper: 0,
+            prgROMSize: (header[4] as usize)*PRG_BANK_SIZE,
+            chrROMSize,
+            //flags8 is rarely set, a value of 0 means 8KB for compatibility
+            prgRAMSize: if flags6.flag(1) {0} else {(flags8.max(1) as usize)*PRG_RAM_BANK_SIZE},
+            prgNVRAMSize: if flags6.flag(1) {(flags8.max(1) as usize)*PRG_RAM_BANK_SIZE} else {0},
+            chrRAMSize: if chrROMSize == 0 {CHR_BANK_SIZE} else {0},
+            chrNVRAMSize: 0,
+            mirroring: Header::mirroring(flags6),
+            battery: flags6.flag(1),
+            trainer: flags6.flag(2),
+            busConflicts: flags10.flag(5),
+            timing: if flags9.flag(0) {Timing::PAL} else {Timing::NTSC},
+            console: if flags7.flag(0){
+                ConsoleType::VsSystem
+            } else if flags7.flag(1){
+                ConsoleType::Playchoice10
+            } else {
+                ConsoleType::NES
+            },
+            vsPPUType: 0,
+            vsHardwareType: 0,
+            miscROMs: 0,
+            expansionDevice: 0,
+        }
+    }
+
+    fn parseNes2(header: &[u8]) -> Result<Header, CartridgeError>{
+        let flags6 = header[6];
+        let flags7 = header[7];
+
+        let console = match flags7 & 3{
+            0 => ConsoleType::NES,
+            1 => ConsoleType::VsSystem,
+            2 => ConsoleType::Playchoice10,
+            _ => ConsoleType::Extended(header[13] & 0x0F),
+        };
+        let (vsPPUType, vsHardwareType) = if console == ConsoleType::VsSystem{
+            (header[13] & 0x0F, header[13] >> 4)
+        } else {
+            (0, 0)
+        };
+
+        let mapper = ((flags6 >> 4) as u16) | ((flags7 & 0xF0) as u16) | (((header[8] & 0x0F) as u16) << 8);
+        let submapper = header[8] >> 4;
+
+        Ok(Header{
+            format: HeaderFormat::Nes2,
+            mapper,
+            submapper,
+            prgROMSize: Header::romSize(header[4], header[9] & 0x0F, PRG_BANK_SIZE)?,
+            chrROMSize: Header::romSize(header[5], header[9] >> 4, CHR_BANK_SIZE)?,
+            prgRAMSize: Header::ramSize(header[10] & 0x0F),
+            prgNVRAMSize: Header::ramSize(header[10] >> 4),
+            chrRAMSize: Header::ramSize(header[11] & 0x0F),
+            chrNVRAMSize: Header::ramSize(header[11] >> 4),
+            mirroring: Header::mirroring(flags6),
+            battery: flags6.flag(1),
+            trainer: flags6.flag(2),
+            //UxROM, CNROM and AxROM use submapper 1 for boards without bus conflicts and 2 for boards with them
+            busConflicts: matches!(mapper, 2 | 3 | 7) && submapper == 2,
+            timing: match header[12] & 3{
+                0 => Timing::NTSC,
+                1 => Timing::PAL,
+                2 => Timing::MultiRegion,
+                _ => Timing::Dendy,
+            },
+            console,
+            vsPPUType,
+            vsHardwareType,
+            miscROMs: header[14] & 3,
+            expansionDevice: header[15] & 0x3F,
+        })
+    }
+
+    fn mirroring(flags6: u8) -> Mirroring{
+        if flags6.flag(3){
+            Mirroring::FourScreen
+        } else if flags6.flag(0){
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        }
+    }
+
+    //an MSB nibble of $F switches the LSB byte to the exponent-multiplier notation: EEEEEEMM => 2^E * (MM*2+1)
+    fn romSize(lsb: u8, msb: u8, bankSize: usize) -> Result<usize, CartridgeError>{
+        if msb == 0x0F{
+            let exponent = (lsb >> 2) as u32;
+            let multiplier = ((lsb & 3) as usize)*2 + 1;
+            1usize.checked_shl(exponent)
+                .filter(|size| size.leading_zeros() >= 2) //the multiplier goes up to 7
+                .map(|size| size*multiplier)
+                .ok_or(CartridgeError::RomTooLarge)
+        }
+        else {
+            Ok((((msb as usize) << 8) | lsb as usize)*bankSize)
+        }
+    }
+
+    fn ramSize(shift: u8) -> usize{
+        if shift == 0 {0} else {64 << shift}
+    }
+
+    //exponent notation sizes each fit in a usize but their sum doesn't have to
+    pub fn romDataSize(&self) -> Result<usize, CartridgeError>{
+        let trainerSize = if self.trainer {512} else {0};
+        self.prgROMSize.checked_add(self.chrROMSize)
+            .and_then(|size| size.checked_add(trainerSize))
+            .ok_or(CartridgeError::RomTooLarge)
+    }
+}
+
+impl Default for Header{
+    fn default() -> Self{
+        Header{
+            format: HeaderFormat::INes,
+            mapper: 0,
+            submapper: 0,
+            prgROMSize: 0,
+            chrROMSize: 0,
+            prgRAMSize: 0,
+            prgNVRAMSize: 0,
+            chrRAMSize: 0,
+            chrNVRAMSize: 0,
+            mirroring: Mirroring::Horizontal,
+            battery: false,
+            trainer: false,
+            busConflicts: false,
+            timing: Timing::NTSC,
+            console: ConsoleType::NES,
+            vsPPUType: 0,
+            vsHardwareType: 0,
+            miscROMs: 0,
+            expansionDevice: 0,
+        }
+    }
+}
+
+impl fmt::Display for Header{
+    fn fmt(&self, f:&mut fmt::Formatter<'_>)->fmt::Result{
+        let format = match self.format{
+            HeaderFormat::INes => "iNES",
+            HeaderFormat::Nes2 => "NES 2.0",
+        };
+        write!(f, "{format} mapper {}.{} PRG {}KB CHR {}KB {:?} {:?}", self.mapper, self.submapper, self.prgROMSize/1024, self.chrROMSize/1024, self.mirroring, self.timing)?;
+        if self.battery{
+            write!(f, " battery")?;
+        }
+        if self.trainer{
+            write!(f, " trainer")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::Cartridge_NES::Cartridge;
+
+    //NES 2.0 header with exponent notation for both ROM sizes
+    fn nes2Header(prgLSB: u8, chrLSB: u8) -> Vec<u8>{
+        let mut rom = vec![b'N', b'E', b'S', 0x1A, prgLSB, chrLSB, 0, 0x08, 0, 0xFF, 0, 0, 0, 0, 0, 0];
+        rom.resize(80, 0);
+        rom
+    }
+
+    #[test]
+    fn exponentSizes(){
+        let header = Header::parse(&nes2Header(0x0A, 0x05)).unwrap();
+        assert_eq!(header.prgROMSize, 5 << 2);
+        assert_eq!(header.chrROMSize, 3 << 1);
+        assert_eq!(header.romDataSize().unwrap(), 26);
+    }
+
+    #[test]
+    fn romSizeSumOverflows(){
+        //PRG 5*2^61 + CHR 3*2^61 wraps to 0 in a usize
+        let rom = nes2Header(61 << 2 | 2, 61 << 2 | 1);
+        let header = Header::parse(&rom).unwrap();
+        assert!(matches!(header.romDataSize(), Err(CartridgeError::RomTooLarge)));
+        assert!(matches!(Cartridge::fromBytes(&rom), Err(CartridgeError::RomTooLarge)));
+    }
+
+    #[test]
+    fn romLargerThanFile(){
+        let rom = nes2Header(61 << 2 | 3, 0);
+        assert!(matches!(Cartridge::fromBytes(&rom), Err(CartridgeError::Truncated{found: 80, ..})));
+    }
+}
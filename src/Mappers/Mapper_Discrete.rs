@@ -0,0 +1,266 @@
+use crate::Mappers::*;
+
+//boards built from a latch and a few logic chips
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DiscreteBoard{
+    UxROM,       //mapper 2
+    CNROM,       //mapper 3
+    AxROM,       //mapper 7
+    ColorDreams, //mapper 11
+    BNROM,       //mapper 34
+    NINA001,     //mapper 34 with CHR ROM
+    GxROM,       //mapper 66
+    Camerica,    //mapper 71
+    FireHawk,    //mapper 71 with one-screen mirroring control
+    JalecoJF11,  //mapper 140
+}
+
+#[derive(Clone)]
+pub struct Discrete{
+    data: CartridgeData,
+    board: DiscreteBoard,
+    prgBank: usize,
+    chrBank0: usize,
+    chrBank1: usize,
+    mirroring: Mirroring,
+}
+
+impl Discrete{
+    pub fn new(board: DiscreteBoard, data: CartridgeData, mirroring: Mirroring) -> Self{
+        let mirroring = if board == DiscreteBoard::AxROM {Mirroring::SingleScreenA} else {mirroring};
+        Discrete{
+            data,
+            board,
+            prgBank: 0,
+            chrBank0: 0,
+            chrBank1: 1,
+            mirroring,
+        }
+    }
+    
+    fn lastPrgBank(&self) -> usize{
+        self.data.prgROM.len()/0x4000 - 1
+    }
+    
+    fn prgRead(&self, adr: u16) -> u8{
+        let offset = adr as usize - 0x8000;
+        match self.board{
+            //16KB switchable bank at $8000, last bank fixed at $C000
+            DiscreteBoard::UxROM | DiscreteBoard::Camerica | DiscreteBoard::FireHawk => {
+                let bank = if adr < 0xC000 {self.prgBank} else {self.lastPrgBank()};
+                self.data.prgRead(bank, 0x4000, offset & 0x3FFF)
+            }
+            DiscreteBoard::CNROM => self.data.prgRead(0, 0, offset % self.data.prgROM.len()),
+            //32KB switchable bank
+            _ => self.data.prgRead(self.prgBank, 0x8000, offset),
+        }
+    }
+    
+    fn chrOffset(&self, adr: u16) -> (usize, usize, usize){
+        let adr = adr as usize & 0x1FFF;
+        match self.board{
+            DiscreteBoard::NINA001 => {
+                let bank = if adr < 0x1000 {self.chrBank0} else {self.chrBank1};
+                (bank, 0x1000, adr & 0x0FFF)
+            }
+            _ => (self.chrBank0, 0x2000, adr),
+        }
+    }
+    
+    fn writeLatch(&mut self, data: u8){
+        match self.board{
+            DiscreteBoard::UxROM | DiscreteBoard::Camerica | DiscreteBoard::FireHawk => self.prgBank = data as usize,
+            DiscreteBoard::CNROM => self.chrBank0 = data as usize,
+            DiscreteBoard::AxROM => {
+                self.prgBank = (data & 7) as usize;
+                self.mirroring = if data & 0x10 == 0 {Mirroring::SingleScreenA} else {Mirroring::SingleScreenB};
+            }
+            DiscreteBoard::ColorDreams => {
+                self.prgBank = (data & 3) as usize;
+                self.chrBank0 = (data >> 4) as usize;
+            }
+            DiscreteBoard::BNROM => self.prgBank = data as usize,
+            DiscreteBoard::GxROM | DiscreteBoard::JalecoJF11 => {
+                self.prgBank = ((data >> 4) & 3) as usize;
+                self.chrBank0 = (data & 0x0F) as usize;
+            }
+            DiscreteBoard::NINA001 => (),
+        }
+    }
+}
+
+impl Mapper for Discrete{
+    fn cpuRead(&mut self, adr: u16) -> u8{
+        match adr{
+            0x6000..=0x7FFF => self.data.prgRAMRead(adr as usize - 0x6000).unwrap_or(0),
+            0x8000..=0xFFFF => self.prgRead(adr),
+            _ => 0,
+        }
+    }
+    
+    fn cpuWrite(&mut self, adr: u16, data: u8){
+        match (self.board, adr){
+            (DiscreteBoard::JalecoJF11, 0x6000..=0x7FFF) => self.writeLatch(data),
+            (DiscreteBoard::NINA001, 0x6000..=0x7FFF) => {
+                //the registers are written through to the RAM
+                self.data.prgRAMWrite(adr as usize - 0x6000, data);
+                match adr{
+                    0x7FFD => self.prgBank = (data & 1) as usize,
+                    0x7FFE => self.chrBank0 = (data & 0x0F) as usize,
+                    0x7FFF => self.chrBank1 = (data & 0x0F) as usize,
+                    _ => (),
+                }
+            }
+            (_, 0x6000..=0x7FFF) => self.data.prgRAMWrite(adr as usize - 0x6000, data),
+            (DiscreteBoard::JalecoJF11 | DiscreteBoard::NINA001, _) => (),
+            //Fire Hawk's board has one-screen mirroring control at $8000-$9FFF, the other Codemasters boards ignore it
+            (DiscreteBoard::FireHawk, 0x8000..=0x9FFF) => {
+                self.mirroring = if data & 0x10 == 0 {Mirroring::SingleScreenA} else {Mirroring::SingleScreenB};
+            }
+            (DiscreteBoard::Camerica | DiscreteBoard::FireHawk, 0x8000..=0xBFFF) => (),
+            (_, 0x8000..=0xFFFF) => self.writeLatch(data),
+            _ => (),
+        }
+    }
+    
+    fn ppuRead(&mut self, adr: u16) -> u8{
+        let (bank, bankSize, offset) = self.chrOffset(adr);
+        self.data.chrRead(bank, bankSize, offset)
+    }
+    
+    fn ppuWrite(&mut self, adr: u16, data: u8){
+        let (bank, bankSize, offset) = self.chrOffset(adr);
+        self.data.chrWrite(bank, bankSize, offset, data);
+    }
+    
+    fn mirroring(&self) -> Mirroring{
+        self.mirroring
+    }
+    
+    fn data(&self) -> &CartridgeData{
+        &self.data
+    }
+    
+    fn dataMut(&mut self) -> &mut CartridgeData{
+        &mut self.data
+    }
+    
+    fn cloneBox(&self) -> Box<dyn Mapper>{
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use DiscreteBoard::*;
+
+    //16KB PRG banks and 4KB CHR banks, each filled with its number
+    fn discrete(board: DiscreteBoard, prgBanks: usize, chrBanks: usize) -> Discrete{
+        let prgROM = (0..prgBanks).flat_map(|bank| vec![bank as u8; 0x4000]).collect();
+        let chr = (0..chrBanks).flat_map(|bank| vec![bank as u8; 0x1000]).collect();
+        Discrete::new(board, CartridgeData{prgROM, chr, chrIsRAM: false, prgRAM: vec![0; 0x2000], vram: Vec::new()}, Mirroring::Vertical)
+    }
+
+    //the banks at $8000 and $C000, and at PPU $0000 and $1000
+    fn banks(mapper: &mut Discrete) -> ([u8; 2], [u8; 2]){
+        ([mapper.cpuRead(0x8000), mapper.cpuRead(0xC000)], [mapper.ppuRead(0x0000), mapper.ppuRead(0x1000)])
+    }
+
+    #[test]
+    fn uxrom(){
+        let mut mapper = discrete(UxROM, 8, 2);
+        mapper.cpuWrite(0x8000, 3);
+        assert_eq!(banks(&mut mapper).0, [3, 7]);
+    }
+
+    #[test]
+    fn cnrom(){
+        let mut mapper = discrete(CNROM, 2, 8);
+        mapper.cpuWrite(0x8000, 2);
+        assert_eq!(banks(&mut mapper), ([0, 1], [4, 5]));
+    }
+
+    //32KB PRG banks, bit 4 picks the CIRAM page
+    #[test]
+    fn axrom(){
+        let mut mapper = discrete(AxROM, 16, 2);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenA);
+        mapper.cpuWrite(0x8000, 0x13);
+        assert_eq!(banks(&mut mapper).0, [6, 7]);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenB);
+        mapper.cpuWrite(0x8000, 0x02);
+        assert_eq!(banks(&mut mapper).0, [4, 5]);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenA);
+    }
+
+    #[test]
+    fn colorDreams(){
+        let mut mapper = discrete(ColorDreams, 8, 8);
+        mapper.cpuWrite(0x8000, 0x21);
+        assert_eq!(banks(&mut mapper), ([2, 3], [4, 5]));
+    }
+
+    #[test]
+    fn bnrom(){
+        let mut mapper = discrete(BNROM, 8, 2);
+        mapper.cpuWrite(0x8000, 2);
+        assert_eq!(banks(&mut mapper).0, [4, 5]);
+    }
+
+    //the registers are at $7FFD-$7FFF and the writes also land in the RAM under them
+    #[test]
+    fn nina001(){
+        let mut mapper = discrete(NINA001, 4, 16);
+        assert_eq!(banks(&mut mapper), ([0, 1], [0, 1]));
+        mapper.cpuWrite(0x7FFD, 1);
+        mapper.cpuWrite(0x7FFE, 3);
+        mapper.cpuWrite(0x7FFF, 5);
+        assert_eq!(banks(&mut mapper), ([2, 3], [3, 5]));
+        assert_eq!([0x7FFD, 0x7FFE, 0x7FFF].map(|adr| mapper.cpuRead(adr)), [1, 3, 5]);
+        
+        //ROM writes don't touch them
+        mapper.cpuWrite(0x8000, 0);
+        assert_eq!(banks(&mut mapper), ([2, 3], [3, 5]));
+    }
+
+    #[test]
+    fn gxrom(){
+        let mut mapper = discrete(GxROM, 8, 8);
+        mapper.cpuWrite(0x8000, 0x12);
+        assert_eq!(banks(&mut mapper), ([2, 3], [4, 5]));
+    }
+
+    //the bank register is at $C000-$FFFF
+    #[test]
+    fn camerica(){
+        let mut mapper = discrete(Camerica, 8, 2);
+        mapper.cpuWrite(0xC000, 3);
+        assert_eq!(banks(&mut mapper).0, [3, 7]);
+        mapper.cpuWrite(0x8000, 0x10);
+        assert_eq!(banks(&mut mapper).0, [3, 7]);
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+    }
+
+    #[test]
+    fn fireHawk(){
+        let mut mapper = discrete(FireHawk, 8, 2);
+        mapper.cpuWrite(0xC000, 2);
+        assert_eq!(banks(&mut mapper).0, [2, 7]);
+        mapper.cpuWrite(0x8000, 0x10);
+        assert_eq!(banks(&mut mapper).0, [2, 7]);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenB);
+        mapper.cpuWrite(0x9FFF, 0x00);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenA);
+    }
+
+    //the latch is at $6000-$7FFF
+    #[test]
+    fn jalecoJF11(){
+        let mut mapper = discrete(JalecoJF11, 8, 8);
+        mapper.cpuWrite(0x6000, 0x12);
+        assert_eq!(banks(&mut mapper), ([2, 3], [4, 5]));
+        mapper.cpuWrite(0x8000, 0x00);
+        assert_eq!(banks(&mut mapper), ([2, 3], [4, 5]));
+    }
+}
@@ -0,0 +1,48 @@
+use crate::Mappers::*;
+
+//64K of RAM covering the whole CPU address space, used for raw 6502 binaries
+#[derive(Clone)]
+pub struct Flat{
+    data: CartridgeData,
+}
+
+impl Flat{
+    pub fn new(ram: Vec<u8>) -> Self{
+        Flat{
+            data: CartridgeData{prgROM: Vec::new(), chr: Vec::new(), chrIsRAM: false, prgRAM: ram, vram: Vec::new()},
+        }
+    }
+}
+
+impl Mapper for Flat{
+    fn cpuRead(&mut self, adr: u16) -> u8{
+        self.data.prgRAM[adr as usize]
+    }
+    
+    fn cpuWrite(&mut self, adr: u16, data: u8){
+        self.data.prgRAM[adr as usize] = data;
+    }
+    
+    fn ppuRead(&mut self, _adr: u16) -> u8{
+        0
+    }
+    
+    fn ppuWrite(&mut self, _adr: u16, _data: u8){
+    }
+    
+    fn mirroring(&self) -> Mirroring{
+        Mirroring::Horizontal
+    }
+    
+    fn data(&self) -> &CartridgeData{
+        &self.data
+    }
+    
+    fn dataMut(&mut self) -> &mut CartridgeData{
+        &mut self.data
+    }
+    
+    fn cloneBox(&self) -> Box<dyn Mapper>{
+        Box::new(self.clone())
+    }
+}
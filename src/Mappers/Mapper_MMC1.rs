@@ -0,0 +1,304 @@
+use crate::Mappers::*;
+
+const PRG_BANK_SIZE: usize = 0x4000;
+const CHR_BANK_SIZE: usize = 0x1000;
+const PRG_RAM_BANK_SIZE: usize = 0x2000;
+const OUTER_PRG_SIZE: usize = 0x40000;
+
+//mapper 1: SxROM boards
+//the CHR bank registers also drive extra lines on some boards:
+//  SNROM: bit 4 disables the PRG RAM
+//  SOROM: bit 3 selects the 8KB PRG RAM bank
+//  SUROM: bit 4 selects the 256KB PRG ROM bank
+//  SXROM: bit 4 selects the 256KB PRG ROM bank, bits 2-3 the 8KB PRG RAM bank
+#[derive(Clone)]
+pub struct MMC1{
+    data: CartridgeData,
+    shift: u8,
+    shiftCount: u8,
+    control: u8,
+    chrBank0: u8,
+    chrBank1: u8,
+    prgBank: u8,
+    
+    cycle: u64,
+    lastWriteCycle: Option<u64>,
+    chrHigh: bool, //A12 of the last PPU access, selects which CHR register drives the extra lines in 4KB mode
+}
+
+impl MMC1{
+    pub fn new(data: CartridgeData) -> Self{
+        MMC1{
+            data,
+            shift: 0,
+            shiftCount: 0,
+            control: 0x0C, //PRG mode 3 at power on
+            chrBank0: 0,
+            chrBank1: 0,
+            prgBank: 0,
+            cycle: 0,
+            lastWriteCycle: None,
+            chrHigh: false,
+        }
+    }
+    
+    fn chr4KMode(&self) -> bool{
+        self.control & 0x10 != 0
+    }
+    
+    //CHR register currently seen by the board's extra lines
+    fn boardBank(&self) -> u8{
+        if self.chr4KMode() && self.chrHigh {self.chrBank1} else {self.chrBank0}
+    }
+    
+    fn outerPrgBank(&self) -> usize{
+        if self.data.prgROM.len() > OUTER_PRG_SIZE{
+            ((self.boardBank() >> 4) & 1) as usize * (OUTER_PRG_SIZE/PRG_BANK_SIZE)
+        }
+        else {
+            0
+        }
+    }
+    
+    fn prgRAMBank(&self) -> usize{
+        match self.data.prgRAM.len(){
+            0x8000 => ((self.boardBank() >> 2) & 3) as usize, //SXROM
+            0x4000 => ((self.boardBank() >> 3) & 1) as usize, //SOROM
+            _ => 0,
+        }
+    }
+    
+    fn prgRAMEnabled(&self) -> bool{
+        //SNROM uses bit 4 of the CHR register to disable the RAM, boards with 512KB of PRG use it for the outer bank
+        let snromDisable = self.data.chrIsRAM && self.data.prgROM.len() <= OUTER_PRG_SIZE && self.boardBank() & 0x10 != 0;
+        self.prgBank & 0x10 == 0 && !snromDisable
+    }
+    
+    fn prgBankAt(&self, adr: u16) -> usize{
+        let bank = (self.prgBank & 0x0F) as usize;
+        let lastBank = (OUTER_PRG_SIZE.min(self.data.prgROM.len()) / PRG_BANK_SIZE).max(1) - 1;
+        let inner = match ((self.control >> 2) & 3, adr >= 0xC000){
+            (0 | 1, high) => (bank & !1) | high as usize, //32KB mode ignores the low bit
+            (2, false) => 0,
+            (2, true) => bank,
+            (_, false) => bank,
+            (_, true) => lastBank,
+        };
+        self.outerPrgBank() + inner
+    }
+    
+    fn chrBankAt(&self, adr: u16) -> usize{
+        let high = adr & 0x1000 != 0;
+        if self.chr4KMode(){
+            if high {self.chrBank1 as usize} else {self.chrBank0 as usize}
+        }
+        else {
+            ((self.chrBank0 & !1) as usize) | high as usize
+        }
+    }
+    
+    fn writeRegister(&mut self, adr: u16, data: u8){
+        match adr{
+            0x8000..=0x9FFF => self.control = data,
+            0xA000..=0xBFFF => self.chrBank0 = data,
+            0xC000..=0xDFFF => self.chrBank1 = data,
+            _ => self.prgBank = data,
+        }
+    }
+}
+
+impl Mapper for MMC1{
+    fn cpuRead(&mut self, adr: u16) -> u8{
+        match adr{
+            0x6000..=0x7FFF => {
+                if !self.prgRAMEnabled(){
+                    return 0;
+                }
+                let offset = self.prgRAMBank()*PRG_RAM_BANK_SIZE + (adr as usize - 0x6000);
+                self.data.prgRAMRead(offset).unwrap_or(0)
+            }
+            0x8000..=0xFFFF => self.data.prgRead(self.prgBankAt(adr), PRG_BANK_SIZE, adr as usize & 0x3FFF),
+            _ => 0,
+        }
+    }
+    
+    fn cpuWrite(&mut self, adr: u16, data: u8){
+        match adr{
+            0x6000..=0x7FFF if self.prgRAMEnabled() => {
+                let offset = self.prgRAMBank()*PRG_RAM_BANK_SIZE + (adr as usize - 0x6000);
+                self.data.prgRAMWrite(offset, data);
+            }
+            0x8000..=0xFFFF => {
+                //the serial port ignores writes on consecutive cycles (the second write of RMW instructions)
+                let consecutive = self.lastWriteCycle.is_some_and(|last| self.cycle - last <= 1);
+                self.lastWriteCycle = Some(self.cycle);
+                if consecutive{
+                    return;
+                }
+                
+                if data & 0x80 != 0{
+                    self.shift = 0;
+                    self.shiftCount = 0;
+                    self.control |= 0x0C;
+                    return;
+                }
+                
+                self.shift |= (data & 1) << self.shiftCount;
+                self.shiftCount += 1;
+                if self.shiftCount == 5{
+                    self.writeRegister(adr, self.shift);
+                    self.shift = 0;
+                    self.shiftCount = 0;
+                }
+            }
+            _ => (),
+        }
+    }
+    
+    fn ppuRead(&mut self, adr: u16) -> u8{
+        self.data.chrRead(self.chrBankAt(adr), CHR_BANK_SIZE, adr as usize & 0x0FFF)
+    }
+    
+    fn ppuWrite(&mut self, adr: u16, data: u8){
+        self.data.chrWrite(self.chrBankAt(adr), CHR_BANK_SIZE, adr as usize & 0x0FFF, data);
+    }
+    
+    fn mirroring(&self) -> Mirroring{
+        match self.control & 3{
+            0 => Mirroring::SingleScreenA,
+            1 => Mirroring::SingleScreenB,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        }
+    }
+    
+    fn data(&self) -> &CartridgeData{
+        &self.data
+    }
+    
+    fn dataMut(&mut self) -> &mut CartridgeData{
+        &mut self.data
+    }
+    
+    fn cloneBox(&self) -> Box<dyn Mapper>{
+        Box::new(self.clone())
+    }
+    
+    fn cpuCycle(&mut self){
+        self.cycle += 1;
+    }
+    
+    fn ppuA12(&mut self, high: bool){
+        self.chrHigh = high;
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    //every 16KB PRG bank is filled with its number
+    fn mmc1(prgBanks: usize, prgRAM: usize) -> MMC1{
+        let prgROM = (0..prgBanks).flat_map(|bank| vec![bank as u8; PRG_BANK_SIZE]).collect();
+        MMC1::new(CartridgeData{prgROM, chr: vec![0; 0x2000], chrIsRAM: true, prgRAM: vec![0; prgRAM], vram: Vec::new()})
+    }
+
+    //one write of the serial port, a few cycles after the last one like a store instruction
+    fn serialWrite(mapper: &mut MMC1, adr: u16, data: u8){
+        for _ in 0..4{
+            mapper.cpuCycle();
+        }
+        mapper.cpuWrite(adr, data);
+    }
+
+    //five writes, low bit first
+    fn writeRegister(mapper: &mut MMC1, adr: u16, value: u8){
+        for bit in 0..5{
+            serialWrite(mapper, adr, value >> bit);
+        }
+    }
+
+    fn banks(mapper: &mut MMC1) -> (u8, u8){
+        (mapper.cpuRead(0x8000), mapper.cpuRead(0xC000))
+    }
+
+    #[test]
+    fn serialPort(){
+        let mut mapper = mmc1(16, 0x2000);
+        for bit in 0..4{
+            serialWrite(&mut mapper, 0xE000, 3 >> bit);
+            assert_eq!(banks(&mut mapper), (0, 15), "write {bit}");
+        }
+        serialWrite(&mut mapper, 0xE000, 0);
+        assert_eq!(banks(&mut mapper), (3, 15));
+        
+        //bit 7 drops the bits shifted in so far and goes back to PRG mode 3
+        writeRegister(&mut mapper, 0x8000, 0x08);
+        assert_eq!(banks(&mut mapper), (0, 3));
+        serialWrite(&mut mapper, 0xE000, 1);
+        serialWrite(&mut mapper, 0xE000, 0x80);
+        assert_eq!(banks(&mut mapper), (3, 15));
+        writeRegister(&mut mapper, 0xE000, 5);
+        assert_eq!(banks(&mut mapper), (5, 15));
+    }
+
+    //the second write of a read-modify-write instruction comes on the next cycle and is ignored
+    #[test]
+    fn consecutiveWritesIgnored(){
+        let mut mapper = mmc1(16, 0x2000);
+        serialWrite(&mut mapper, 0xE000, 0);
+        mapper.cpuCycle();
+        mapper.cpuWrite(0xE000, 1);
+        for bit in 1..5{
+            serialWrite(&mut mapper, 0xE000, 6 >> bit);
+        }
+        assert_eq!(banks(&mut mapper), (6, 15));
+        
+        //that includes the reset bit
+        serialWrite(&mut mapper, 0xE000, 1);
+        mapper.cpuCycle();
+        mapper.cpuWrite(0xE000, 0x80);
+        for bit in 1..5{
+            serialWrite(&mut mapper, 0xE000, 7 >> bit);
+        }
+        assert_eq!(banks(&mut mapper), (7, 15));
+    }
+
+    #[test]
+    fn prgModes(){
+        let mut mapper = mmc1(16, 0x2000);
+        writeRegister(&mut mapper, 0xE000, 5);
+        for (mode, expected) in [(0, (4, 5)), (1, (4, 5)), (2, (0, 5)), (3, (5, 15))]{
+            writeRegister(&mut mapper, 0x8000, mode << 2);
+            assert_eq!(banks(&mut mapper), expected, "mode {mode}");
+        }
+    }
+
+    //SUROM: bit 4 of the CHR register picks the 256KB half, the fixed bank is the last of that half
+    #[test]
+    fn outerPrgBank(){
+        let mut mapper = mmc1(32, 0x2000);
+        writeRegister(&mut mapper, 0xE000, 2);
+        assert_eq!(banks(&mut mapper), (2, 15));
+        writeRegister(&mut mapper, 0xA000, 0x10);
+        assert_eq!(banks(&mut mapper), (18, 31));
+    }
+
+    //SOROM: bit 3 of the CHR register picks one of 2 8KB banks, SXROM: bits 2-3 one of 4
+    #[test]
+    fn prgRAMBanks(){
+        for (size, shift) in [(0x4000, 3), (0x8000, 2)]{
+            let mut mapper = mmc1(16, size);
+            let count = size / PRG_RAM_BANK_SIZE;
+            for bank in 0..count{
+                writeRegister(&mut mapper, 0xA000, (bank << shift) as u8);
+                mapper.cpuWrite(0x6000, 0x10 + bank as u8);
+            }
+            for bank in 0..count{
+                writeRegister(&mut mapper, 0xA000, (bank << shift) as u8);
+                assert_eq!(mapper.cpuRead(0x6000), 0x10 + bank as u8, "{size:X} bank {bank}");
+            }
+            assert_eq!(mapper.data.prgRAM[(count - 1)*PRG_RAM_BANK_SIZE], 0x10 + count as u8 - 1);
+        }
+    }
+}
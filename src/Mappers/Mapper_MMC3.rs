@@ -0,0 +1,267 @@
+use crate::Mappers::*;
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+const A12_FILTER_CYCLES: u64 = 3; //A12 has to stay low this many M2 cycles before a rising edge clocks the counter
+
+//mapper 4: TxROM boards
+#[derive(Clone)]
+pub struct MMC3{
+    data: CartridgeData,
+    bankSelect: u8,
+    registers: [u8; 8],
+    mirroring: Mirroring,
+    fourScreen: bool,
+    prgRAMProtect: u8,
+    
+    irqLatch: u8,
+    irqCounter: u8,
+    irqReload: bool,
+    irqEnabled: bool,
+    irqLine: bool,
+    
+    cycle: u64,
+    a12: bool,
+    a12LowCycle: u64,
+}
+
+impl MMC3{
+    pub fn new(data: CartridgeData, mirroring: Mirroring) -> Self{
+        MMC3{
+            data,
+            bankSelect: 0,
+            registers: [0, 2, 4, 5, 6, 7, 0, 1],
+            mirroring,
+            fourScreen: mirroring == Mirroring::FourScreen,
+            prgRAMProtect: 0x80,
+            irqLatch: 0,
+            irqCounter: 0,
+            irqReload: false,
+            irqEnabled: false,
+            irqLine: false,
+            cycle: 0,
+            a12: false,
+            a12LowCycle: 0,
+        }
+    }
+    
+    fn prgBankAt(&self, adr: u16) -> usize{
+        let lastBank = self.data.prgROM.len()/PRG_BANK_SIZE - 1;
+        let prgMode = self.bankSelect & 0x40 != 0;
+        match ((adr - 0x8000)/0x2000, prgMode){
+            (0, false) | (2, true) => (self.registers[6] & 0x3F) as usize,
+            (0, true) | (2, false) => lastBank - 1,
+            (1, _) => (self.registers[7] & 0x3F) as usize,
+            _ => lastBank,
+        }
+    }
+    
+    fn chrBankAt(&self, adr: u16) -> usize{
+        //CHR inversion swaps the 2KB and the 1KB halves
+        let adr = if self.bankSelect & 0x80 != 0 {adr ^ 0x1000} else {adr};
+        match adr/0x400{
+            0 => (self.registers[0] & 0xFE) as usize,
+            1 => (self.registers[0] | 1) as usize,
+            2 => (self.registers[1] & 0xFE) as usize,
+            3 => (self.registers[1] | 1) as usize,
+            slot => self.registers[(slot - 2) as usize] as usize,
+        }
+    }
+    
+    fn clockCounter(&mut self){
+        if self.irqCounter == 0 || self.irqReload{
+            self.irqCounter = self.irqLatch;
+            self.irqReload = false;
+        }
+        else {
+            self.irqCounter -= 1;
+        }
+        
+        if self.irqCounter == 0 && self.irqEnabled{
+            self.irqLine = true;
+        }
+    }
+}
+
+impl Mapper for MMC3{
+    fn cpuRead(&mut self, adr: u16) -> u8{
+        match adr{
+            0x6000..=0x7FFF if self.prgRAMProtect & 0x80 != 0 => self.data.prgRAMRead(adr as usize - 0x6000).unwrap_or(0),
+            0x8000..=0xFFFF => self.data.prgRead(self.prgBankAt(adr), PRG_BANK_SIZE, adr as usize & 0x1FFF),
+            _ => 0,
+        }
+    }
+    
+    fn cpuWrite(&mut self, adr: u16, data: u8){
+        let even = adr & 1 == 0;
+        match adr{
+            //bit 7 enables the chip, bit 6 protects it from writes
+            0x6000..=0x7FFF if self.prgRAMProtect & 0xC0 == 0x80 => self.data.prgRAMWrite(adr as usize - 0x6000, data),
+            0x8000..=0x9FFF if even => self.bankSelect = data,
+            0x8000..=0x9FFF => self.registers[(self.bankSelect & 7) as usize] = data,
+            0xA000..=0xBFFF if even && !self.fourScreen => {
+                self.mirroring = if data & 1 == 0 {Mirroring::Vertical} else {Mirroring::Horizontal};
+            }
+            0xA000..=0xBFFF if even => (),
+            0xA000..=0xBFFF => self.prgRAMProtect = data,
+            0xC000..=0xDFFF if even => self.irqLatch = data,
+            0xC000..=0xDFFF => {
+                self.irqCounter = 0;
+                self.irqReload = true;
+            }
+            0xE000..=0xFFFF if even => {
+                self.irqEnabled = false;
+                self.irqLine = false; //acknowledge
+            }
+            0xE000..=0xFFFF => self.irqEnabled = true,
+            _ => (),
+        }
+    }
+    
+    fn ppuRead(&mut self, adr: u16) -> u8{
+        self.data.chrRead(self.chrBankAt(adr), CHR_BANK_SIZE, adr as usize & 0x03FF)
+    }
+    
+    fn ppuWrite(&mut self, adr: u16, data: u8){
+        self.data.chrWrite(self.chrBankAt(adr), CHR_BANK_SIZE, adr as usize & 0x03FF, data);
+    }
+    
+    fn mirroring(&self) -> Mirroring{
+        self.mirroring
+    }
+    
+    fn data(&self) -> &CartridgeData{
+        &self.data
+    }
+    
+    fn dataMut(&mut self) -> &mut CartridgeData{
+        &mut self.data
+    }
+    
+    fn cloneBox(&self) -> Box<dyn Mapper>{
+        Box::new(self.clone())
+    }
+    
+    fn irq(&self) -> bool{
+        self.irqLine
+    }
+    
+    fn cpuCycle(&mut self){
+        self.cycle += 1;
+    }
+    
+    fn ppuA12(&mut self, high: bool){
+        if high && !self.a12{
+            if self.cycle - self.a12LowCycle >= A12_FILTER_CYCLES{
+                self.clockCounter();
+            }
+        }
+        else if !high && self.a12{
+            self.a12LowCycle = self.cycle;
+        }
+        self.a12 = high;
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    //8 PRG banks and 32 CHR banks, each filled with its number
+    fn mmc3() -> MMC3{
+        let prgROM = (0..8).flat_map(|bank| vec![bank as u8; PRG_BANK_SIZE]).collect();
+        let chr = (0..32).flat_map(|bank| vec![bank as u8; CHR_BANK_SIZE]).collect();
+        MMC3::new(CartridgeData{prgROM, chr, chrIsRAM: false, prgRAM: vec![0; 0x2000], vram: Vec::new()}, Mirroring::Vertical)
+    }
+
+    fn prgBanks(mapper: &mut MMC3) -> [u8; 4]{
+        [0x8000, 0xA000, 0xC000, 0xE000].map(|adr| mapper.cpuRead(adr))
+    }
+
+    fn chrBanks(mapper: &mut MMC3) -> [u8; 8]{
+        std::array::from_fn(|slot| mapper.ppuRead(slot as u16 * 0x400))
+    }
+
+    #[test]
+    fn prgModes(){
+        let mut mapper = mmc3();
+        for (register, bank) in [(6, 2), (7, 3)]{
+            mapper.cpuWrite(0x8000, register);
+            mapper.cpuWrite(0x8001, bank);
+        }
+        assert_eq!(prgBanks(&mut mapper), [2, 3, 6, 7]);
+        //bit 6 swaps $8000 and $C000
+        mapper.cpuWrite(0x8000, 0x40);
+        assert_eq!(prgBanks(&mut mapper), [6, 3, 2, 7]);
+    }
+
+    #[test]
+    fn chrInversion(){
+        let mut mapper = mmc3();
+        //the 2KB banks ignore the low bit
+        for (register, bank) in [5, 6, 10, 11, 12, 13].into_iter().enumerate(){
+            mapper.cpuWrite(0x8000, register as u8);
+            mapper.cpuWrite(0x8001, bank);
+        }
+        assert_eq!(chrBanks(&mut mapper), [4, 5, 6, 7, 10, 11, 12, 13]);
+        //bit 7 puts the 1KB banks at $0000 and the 2KB banks at $1000
+        mapper.cpuWrite(0x8000, 0x80);
+        assert_eq!(chrBanks(&mut mapper), [10, 11, 12, 13, 4, 5, 6, 7]);
+    }
+
+    //$A001: bit 7 enables the PRG RAM, bit 6 protects it from writes
+    #[test]
+    fn prgRAMProtect(){
+        let mut mapper = mmc3();
+        mapper.cpuWrite(0x6000, 0x11);
+        assert_eq!(mapper.cpuRead(0x6000), 0x11);
+        
+        mapper.cpuWrite(0xA001, 0xC0);
+        mapper.cpuWrite(0x6000, 0x22);
+        assert_eq!(mapper.cpuRead(0x6000), 0x11);
+        
+        mapper.cpuWrite(0xA001, 0x00);
+        mapper.cpuWrite(0x6000, 0x33);
+        assert_eq!(mapper.cpuRead(0x6000), 0);
+        
+        mapper.cpuWrite(0xA001, 0x80);
+        assert_eq!(mapper.cpuRead(0x6000), 0x11);
+        mapper.cpuWrite(0x6000, 0x44);
+        assert_eq!(mapper.cpuRead(0x6000), 0x44);
+    }
+
+    //A12 rises after being low for cycles M2 cycles
+    fn a12Rise(mapper: &mut MMC3, cycles: u64){
+        mapper.ppuA12(false);
+        for _ in 0..cycles{
+            mapper.cpuCycle();
+        }
+        mapper.ppuA12(true);
+    }
+
+    #[test]
+    fn a12Filter(){
+        //latch 0: the first counter clock raises the IRQ
+        let mut mapper = mmc3();
+        mapper.cpuWrite(0xC000, 0);
+        mapper.cpuWrite(0xC001, 0);
+        mapper.cpuWrite(0xE001, 0);
+        
+        //rises closer together than 3 M2 cycles are ignored, like the 8 dot pattern of the sprite fetches
+        for _ in 0..8{
+            a12Rise(&mut mapper, A12_FILTER_CYCLES - 1);
+            assert!(!mapper.irq());
+        }
+        a12Rise(&mut mapper, A12_FILTER_CYCLES);
+        assert!(mapper.irq());
+        
+        //acknowledged and enabled again, then the next rise
+        mapper.cpuWrite(0xE000, 0);
+        mapper.cpuWrite(0xE001, 0);
+        assert!(!mapper.irq());
+        a12Rise(&mut mapper, 1);
+        assert!(!mapper.irq());
+        a12Rise(&mut mapper, 10);
+        assert!(mapper.irq());
+    }
+}
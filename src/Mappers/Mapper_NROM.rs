@@ -0,0 +1,59 @@
+use crate::Mappers::*;
+
+const PRG_RAM_WINDOW: usize = 0x2000;
+
+//mapper 0: 16KB (NROM-128, mirrored at $C000) or 32KB (NROM-256) of PRG ROM, 8KB of CHR ROM or RAM, hardwired mirroring
+#[derive(Clone)]
+pub struct NROM{
+    data: CartridgeData,
+    mirroring: Mirroring,
+    prgMask: usize,
+}
+
+impl NROM{
+    pub fn new(data: CartridgeData, mirroring: Mirroring) -> Self{
+        let prgMask = if data.prgROM.len() <= 0x4000 {0x3FFF} else {0x7FFF};
+        NROM{data, mirroring, prgMask}
+    }
+}
+
+impl Mapper for NROM{
+    fn cpuRead(&mut self, adr: u16) -> u8{
+        match adr{
+            //Family Basic has only 2 or 4KB of RAM, mirrored in the whole window
+            0x6000..=0x7FFF => self.data.prgRAMRead((adr as usize - 0x6000) % PRG_RAM_WINDOW).unwrap_or(0),
+            0x8000..=0xFFFF => self.data.prgRead(0, 0, (adr as usize - 0x8000) & self.prgMask),
+            _ => 0,
+        }
+    }
+    
+    fn cpuWrite(&mut self, adr: u16, data: u8){
+        if let 0x6000..=0x7FFF = adr{
+            self.data.prgRAMWrite((adr as usize - 0x6000) % PRG_RAM_WINDOW, data);
+        }
+    }
+    
+    fn ppuRead(&mut self, adr: u16) -> u8{
+        self.data.chrRead(0, 0, adr as usize & 0x1FFF)
+    }
+    
+    fn ppuWrite(&mut self, adr: u16, data: u8){
+        self.data.chrWrite(0, 0, adr as usize & 0x1FFF, data);
+    }
+    
+    fn mirroring(&self) -> Mirroring{
+        self.mirroring
+    }
+    
+    fn data(&self) -> &CartridgeData{
+        &self.data
+    }
+    
+    fn dataMut(&mut self) -> &mut CartridgeData{
+        &mut self.data
+    }
+    
+    fn cloneBox(&self) -> Box<dyn Mapper>{
+        Box::new(self.clone())
+    }
+}
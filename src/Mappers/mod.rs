@@ -0,0 +1,213 @@
+mod Mapper_Flat;
+mod Mapper_NROM;
+mod Mapper_MMC1;
+mod Mapper_MMC3;
+mod Mapper_Discrete;
+
+use crate::Cartridge_NES::CartridgeError;
+use crate::Header_NES::{Header, HeaderFormat};
+
+pub use self::Mapper_Flat::Flat;
+pub use self::Mapper_NROM::NROM;
+pub use self::Mapper_MMC1::MMC1;
+pub use self::Mapper_MMC3::MMC3;
+pub use self::Mapper_Discrete::{Discrete, DiscreteBoard};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mirroring{
+    Horizontal, //vertical arrangement
+    Vertical,   //horizontal arrangement
+    FourScreen,
+    SingleScreenA, //first CIRAM page only
+    SingleScreenB, //second CIRAM page only
+}
+
+impl Mirroring{
+    //maps a nametable address ($2000-$3EFF) to an offset in the 2KB CIRAM (or the 4KB cartridge VRAM for four-screen)
+    pub fn nametableOffset(self, adr: u16) -> usize{
+        let adr = (adr & 0x0FFF) as usize;
+        let table = adr / 0x400;
+        let offset = adr % 0x400;
+        match self{
+            Mirroring::Horizontal => (table/2)*0x400 + offset,
+            Mirroring::Vertical => (table%2)*0x400 + offset,
+            Mirroring::FourScreen => adr,
+            Mirroring::SingleScreenA => offset,
+            Mirroring::SingleScreenB => 0x400 + offset,
+        }
+    }
+}
+
+//memory found on the cartridge board, owned by the mapper
+#[derive(Clone)]
+pub struct CartridgeData{
+    pub prgROM: Vec<u8>,
+    pub chr: Vec<u8>,
+    pub chrIsRAM: bool,
+    pub prgRAM: Vec<u8>,
+    pub vram: Vec<u8>, //extra nametable RAM of four-screen boards
+}
+
+impl CartridgeData{
+    pub fn prgRead(&self, bank: usize, bankSize: usize, offset: usize) -> u8{
+        self.prgROM[(bank*bankSize + offset) % self.prgROM.len()]
+    }
+    
+    pub fn chrRead(&self, bank: usize, bankSize: usize, offset: usize) -> u8{
+        self.chr[(bank*bankSize + offset) % self.chr.len()]
+    }
+    
+    pub fn chrWrite(&mut self, bank: usize, bankSize: usize, offset: usize, data: u8){
+        if self.chrIsRAM{
+            let len = self.chr.len();
+            self.chr[(bank*bankSize + offset) % len] = data;
+        }
+    }
+    
+    pub fn prgRAMRead(&self, offset: usize) -> Option<u8>{
+        if self.prgRAM.is_empty(){
+            return None;
+        }
+        Some(self.prgRAM[offset % self.prgRAM.len()])
+    }
+    
+    pub fn prgRAMWrite(&mut self, offset: usize, data: u8){
+        if !self.prgRAM.is_empty(){
+            let len = self.prgRAM.len();
+            self.prgRAM[offset % len] = data;
+        }
+    }
+}
+
+//a cartridge board as seen from the CPU and the PPU buses
+pub trait Mapper: Send{
+    fn cpuRead(&mut self, adr: u16) -> u8;
+    fn cpuWrite(&mut self, adr: u16, data: u8);
+    
+    //pattern tables ($0000-$1FFF)
+    fn ppuRead(&mut self, adr: u16) -> u8;
+    fn ppuWrite(&mut self, adr: u16, data: u8);
+    
+    fn mirroring(&self) -> Mirroring;
+    
+    fn data(&self) -> &CartridgeData;
+    fn dataMut(&mut self) -> &mut CartridgeData;
+    
+    //boxed copy of the board with all its state, for cloning a whole machine
+    fn cloneBox(&self) -> Box<dyn Mapper>;
+    
+    //nametables ($2000-$3EFF), boards with their own VRAM can override these
+    fn nametableRead(&mut self, adr: u16, ciram: &[u8]) -> u8{
+        let mirroring = self.mirroring();
+        let offset = mirroring.nametableOffset(adr);
+        if mirroring == Mirroring::FourScreen && !self.data().vram.is_empty(){
+            return self.data().vram[offset];
+        }
+        ciram[offset % ciram.len()]
+    }
+    
+    fn nametableWrite(&mut self, adr: u16, data: u8, ciram: &mut [u8]){
+        let mirroring = self.mirroring();
+        let offset = mirroring.nametableOffset(adr);
+        if mirroring == Mirroring::FourScreen && !self.data().vram.is_empty(){
+            self.dataMut().vram[offset] = data;
+            return;
+        }
+        let len = ciram.len();
+        ciram[offset % len] = data;
+    }
+    
+    //level of the IRQ line going to the CPU
+    fn irq(&self) -> bool{
+        false
+    }
+    
+    //called once per CPU cycle (M2)
+    fn cpuCycle(&mut self){
+    }
+    
+    //called on every PPU bus access with the state of the A12 address line
+    fn ppuA12(&mut self, _high: bool){
+    }
+}
+
+impl Clone for Box<dyn Mapper>{
+    fn clone(&self) -> Self{
+        self.cloneBox()
+    }
+}
+
+pub fn newMapper(header: &Header, data: CartridgeData) -> Result<Box<dyn Mapper>, CartridgeError>{
+    match header.mapper{
+        0 => Ok(Box::new(NROM::new(data, header.mirroring))),
+        1 => Ok(Box::new(MMC1::new(data))),
+        4 => Ok(Box::new(MMC3::new(data, header.mirroring))),
+        mapper => {
+            let board = discreteBoard(header, &data).ok_or(CartridgeError::UnsupportedMapper(mapper))?;
+            Ok(Box::new(Discrete::new(board, data, header.mirroring)))
+        }
+    }
+}
+
+fn discreteBoard(header: &Header, data: &CartridgeData) -> Option<DiscreteBoard>{
+    match header.mapper{
+        2 => Some(DiscreteBoard::UxROM),
+        3 => Some(DiscreteBoard::CNROM),
+        7 => Some(DiscreteBoard::AxROM),
+        11 => Some(DiscreteBoard::ColorDreams),
+        //submapper 1 is NINA-001, 2 is BNROM, iNES files can only be told apart by their CHR
+        34 => match header.submapper{
+            1 => Some(DiscreteBoard::NINA001),
+            2 => Some(DiscreteBoard::BNROM),
+            _ if !data.chrIsRAM && data.chr.len() > 0x2000 => Some(DiscreteBoard::NINA001),
+            _ => Some(DiscreteBoard::BNROM),
+        },
+        66 => Some(DiscreteBoard::GxROM),
+        //submapper 1 is Fire Hawk, 0 has hardwired mirroring. iNES files get the mirroring control,
+        //no other mapper 71 game is known to write to $8000-$9FFF
+        71 => match header.submapper{
+            0 if header.format == HeaderFormat::Nes2 => Some(DiscreteBoard::Camerica),
+            _ => Some(DiscreteBoard::FireHawk),
+        },
+        140 => Some(DiscreteBoard::JalecoJF11),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    //submapper None is an iNES header, chrBanks 0 means CHR RAM
+    fn board(mapper: u8, submapper: Option<u8>, chrBanks: u8) -> Option<DiscreteBoard>{
+        let mut bytes = vec![b'N', b'E', b'S', 0x1A, 2, chrBanks, mapper << 4, mapper & 0xF0, 0, 0, 0, 0, 0, 0, 0, 0];
+        if let Some(submapper) = submapper{
+            bytes[7] |= 0x08;
+            bytes[8] = submapper << 4;
+        }
+        let header = Header::parse(&bytes).unwrap();
+        let chr = vec![0; (chrBanks.max(1) as usize)*0x2000];
+        discreteBoard(&header, &CartridgeData{prgROM: vec![0; 0x8000], chr, chrIsRAM: chrBanks == 0, prgRAM: Vec::new(), vram: Vec::new()})
+    }
+
+    #[test]
+    fn mapper34Boards(){
+        //NES 2.0 says which board it is
+        assert_eq!(board(34, Some(1), 0), Some(DiscreteBoard::NINA001));
+        assert_eq!(board(34, Some(2), 8), Some(DiscreteBoard::BNROM));
+        //iNES: NINA-001 is the one with more than 8KB of CHR ROM
+        for submapper in [None, Some(0)]{
+            assert_eq!(board(34, submapper, 0), Some(DiscreteBoard::BNROM));
+            assert_eq!(board(34, submapper, 1), Some(DiscreteBoard::BNROM));
+            assert_eq!(board(34, submapper, 8), Some(DiscreteBoard::NINA001));
+        }
+    }
+
+    #[test]
+    fn mapper71Boards(){
+        assert_eq!(board(71, Some(0), 0), Some(DiscreteBoard::Camerica));
+        assert_eq!(board(71, Some(1), 0), Some(DiscreteBoard::FireHawk));
+        assert_eq!(board(71, None, 0), Some(DiscreteBoard::FireHawk));
+    }
+}
+
@@ -0,0 +1,347 @@
+use std::fmt;
+use Mnemonic::*;
+use AdrMode::*;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Mnemonic{
+    ADC, ALR, ANC, AND, ANE, ARR, ASL, AXS, BCC, BCS, BEQ, BIT, BMI, BNE, BPL, BRK,
+    BVC, BVS, CLC, CLD, CLI, CLV, CMP, CPX, CPY, DCP, DEC, DEX, DEY, EOR, INC, INX,
+    INY, ISC, JAM, JMP, JSR, LAS, LAX, LDA, LDX, LDY, LSR, LXA, NOP, ORA, PHA, PHP,
+    PLA, PLP, RLA, ROL, ROR, RRA, RTI, RTS, SAX, SBC, SEC, SED, SEI, SHA, SHX, SHY,
+    SLO, SRE, STA, STX, STY, TAS, TAX, TAY, TSX, TXA, TXS, TYA,
+}
+
+impl Mnemonic{
+    //what the instruction does with its operand, this decides the bus cycles after the address is known
+    pub fn access(self) -> Access{
+        match self{
+            STA | STX | STY | SAX | SHA | SHX | SHY | TAS => Access::Write,
+            ASL | LSR | ROL | ROR | INC | DEC | SLO | RLA | SRE | RRA | DCP | ISC => Access::RMW,
+            _ => Access::Read,
+        }
+    }
+    
+    //the unstable opcodes and JAM, handled by the UndefinedOpcodePolicy
+    pub fn isUndefined(self) -> bool{
+        matches!(self, ANE | LXA | SHA | SHX | SHY | TAS | LAS | JAM)
+    }
+}
+
+impl fmt::Display for Mnemonic{
+    fn fmt(&self, f:&mut fmt::Formatter<'_>)->fmt::Result{
+        write!(f, "{self:?}")
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AdrMode{
+    Implied,
+    Accumulator,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    Indirect,        //JMP only
+    IndexedIndirect, //(zp,X)
+    IndirectIndexed, //(zp),Y
+    Relative,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Access{
+    Read,
+    Write,
+    RMW,
+}
+
+pub struct Opcode{
+    pub mnemonic: Mnemonic,
+    pub mode: AdrMode,
+    pub bytes: u8,
+    pub cycles: u8,      //without the page cross penalty, and without the taken branch cycle for branches
+    pub pageCross: bool, //one more cycle when the indexing crosses a page (or the branch goes to another page)
+}
+
+pub static OPCODES: [Opcode; 256] = [
+    Opcode{mnemonic: BRK, mode: Implied, bytes: 1, cycles: 7, pageCross: false}, //00
+    Opcode{mnemonic: ORA, mode: IndexedIndirect, bytes: 2, cycles: 6, pageCross: false}, //01
+    Opcode{mnemonic: JAM, mode: Implied, bytes: 1, cycles: 0, pageCross: false}, //02
+    Opcode{mnemonic: SLO, mode: IndexedIndirect, bytes: 2, cycles: 8, pageCross: false}, //03
+    Opcode{mnemonic: NOP, mode: ZeroPage, bytes: 2, cycles: 3, pageCross: false}, //04
+    Opcode{mnemonic: ORA, mode: ZeroPage, bytes: 2, cycles: 3, pageCross: false}, //05
+    Opcode{mnemonic: ASL, mode: ZeroPage, bytes: 2, cycles: 5, pageCross: false}, //06
+    Opcode{mnemonic: SLO, mode: ZeroPage, bytes: 2, cycles: 5, pageCross: false}, //07
+    Opcode{mnemonic: PHP, mode: Implied, bytes: 1, cycles: 3, pageCross: false}, //08
+    Opcode{mnemonic: ORA, mode: Immediate, bytes: 2, cycles: 2, pageCross: false}, //09
+    Opcode{mnemonic: ASL, mode: Accumulator, bytes: 1, cycles: 2, pageCross: false}, //0A
+    Opcode{mnemonic: ANC, mode: Immediate, bytes: 2, cycles: 2, pageCross: false}, //0B
+    Opcode{mnemonic: NOP, mode: Absolute, bytes: 3, cycles: 4, pageCross: false}, //0C
+    Opcode{mnemonic: ORA, mode: Absolute, bytes: 3, cycles: 4, pageCross: false}, //0D
+    Opcode{mnemonic: ASL, mode: Absolute, bytes: 3, cycles: 6, pageCross: false}, //0E
+    Opcode{mnemonic: SLO, mode: Absolute, bytes: 3, cycles: 6, pageCross: false}, //0F
+    Opcode{mnemonic: BPL, mode: Relative, bytes: 2, cycles: 2, pageCross: true}, //10
+    Opcode{mnemonic: ORA, mode: IndirectIndexed, bytes: 2, cycles: 5, pageCross: true}, //11
+    Opcode{mnemonic: JAM, mode: Implied, bytes: 1, cycles: 0, pageCross: false}, //12
+    Opcode{mnemonic: SLO, mode: IndirectIndexed, bytes: 2, cycles: 8, pageCross: false}, //13
+    Opcode{mnemonic: NOP, mode: ZeroPageX, bytes: 2, cycles: 4, pageCross: false}, //14
+    Opcode{mnemonic: ORA, mode: ZeroPageX, bytes: 2, cycles: 4, pageCross: false}, //15
+    Opcode{mnemonic: ASL, mode: ZeroPageX, bytes: 2, cycles: 6, pageCross: false}, //16
+    Opcode{mnemonic: SLO, mode: ZeroPageX, bytes: 2, cycles: 6, pageCross: false}, //17
+    Opcode{mnemonic: CLC, mode: Implied, bytes: 1, cycles: 2, pageCross: false}, //18
+    Opcode{mnemonic: ORA, mode: AbsoluteY, bytes: 3, cycles: 4, pageCross: true}, //19
+    Opcode{mnemonic: NOP, mode: Implied, bytes: 1, cycles: 2, pageCross: false}, //1A
+    Opcode{mnemonic: SLO, mode: AbsoluteY, bytes: 3, cycles: 7, pageCross: false}, //1B
+    Opcode{mnemonic: NOP, mode: AbsoluteX, bytes: 3, cycles: 4, pageCross: true}, //1C
+    Opcode{mnemonic: ORA, mode: AbsoluteX, bytes: 3, cycles: 4, pageCross: true}, //1D
+    Opcode{mnemonic: ASL, mode: AbsoluteX, bytes: 3, cycles: 7, pageCross: false}, //1E
+    Opcode{mnemonic: SLO, mode: AbsoluteX, bytes: 3, cycles: 7, pageCross: false}, //1F
+    Opcode{mnemonic: JSR, mode: Absolute, bytes: 3, cycles: 6, pageCross: false}, //20
+    Opcode{mnemonic: AND, mode: IndexedIndirect, bytes: 2, cycles: 6, pageCross: false}, //21
+    Opcode{mnemonic: JAM, mode: Implied, bytes: 1, cycles: 0, pageCross: false}, //22
+    Opcode{mnemonic: RLA, mode: IndexedIndirect, bytes: 2, cycles: 8, pageCross: false}, //23
+    Opcode{mnemonic: BIT, mode: ZeroPage, bytes: 2, cycles: 3, pageCross: false}, //24
+    Opcode{mnemonic: AND, mode: ZeroPage, bytes: 2, cycles: 3, pageCross: false}, //25
+    Opcode{mnemonic: ROL, mode: ZeroPage, bytes: 2, cycles: 5, pageCross: false}, //26
+    Opcode{mnemonic: RLA, mode: ZeroPage, bytes: 2, cycles: 5, pageCross: false}, //27
+    Opcode{mnemonic: PLP, mode: Implied, bytes: 1, cycles: 4, pageCross: false}, //28
+    Opcode{mnemonic: AND, mode: Immediate, bytes: 2, cycles: 2, pageCross: false}, //29
+    Opcode{mnemonic: ROL, mode: Accumulator, bytes: 1, cycles: 2, pageCross: false}, //2A
+    Opcode{mnemonic: ANC, mode: Immediate, bytes: 2, cycles: 2, pageCross: false}, //2B
+    Opcode{mnemonic: BIT, mode: Absolute, bytes: 3, cycles: 4, pageCross: false}, //2C
+    Opcode{mnemonic: AND, mode: Absolute, bytes: 3, cycles: 4, pageCross: false}, //2D
+    Opcode{mnemonic: ROL, mode: Absolute, bytes: 3, cycles: 6, pageCross: false}, //2E
+    Opcode{mnemonic: RLA, mode: Absolute, bytes: 3, cycles: 6, pageCross: false}, //2F
+    Opcode{mnemonic: BMI, mode: Relative, bytes: 2, cycles: 2, pageCross: true}, //30
+    Opcode{mnemonic: AND, mode: IndirectIndexed, bytes: 2, cycles: 5, pageCross: true}, //31
+    Opcode{mnemonic: JAM, mode: Implied, bytes: 1, cycles: 0, pageCross: false}, //32
+    Opcode{mnemonic: RLA, mode: IndirectIndexed, bytes: 2, cycles: 8, pageCross: false}, //33
+    Opcode{mnemonic: NOP, mode: ZeroPageX, bytes: 2, cycles: 4, pageCross: false}, //34
+    Opcode{mnemonic: AND, mode: ZeroPageX, bytes: 2, cycles: 4, pageCross: false}, //35
+    Opcode{mnemonic: ROL, mode: ZeroPageX, bytes: 2, cycles: 6, pageCross: false}, //36
+    Opcode{mnemonic: RLA, mode: ZeroPageX, bytes: 2, cycles: 6, pageCross: false}, //37
+    Opcode{mnemonic: SEC, mode: Implied, bytes: 1, cycles: 2, pageCross: false}, //38
+    Opcode{mnemonic: AND, mode: AbsoluteY, bytes: 3, cycles: 4, pageCross: true}, //39
+    Opcode{mnemonic: NOP, mode: Implied, bytes: 1, cycles: 2, pageCross: false}, //3A
+    Opcode{mnemonic: RLA, mode: AbsoluteY, bytes: 3, cycles: 7, pageCross: false}, //3B
+    Opcode{mnemonic: NOP, mode: AbsoluteX, bytes: 3, cycles: 4, pageCross: true}, //3C
+    Opcode{mnemonic: AND, mode: AbsoluteX, bytes: 3, cycles: 4, pageCross: true}, //3D
+    Opcode{mnemonic: ROL, mode: AbsoluteX, bytes: 3, cycles: 7, pageCross: false}, //3E
+    Opcode{mnemonic: RLA, mode: AbsoluteX, bytes: 3, cycles: 7, pageCross: false}, //3F
+    Opcode{mnemonic: RTI, mode: Implied, bytes: 1, cycles: 6, pageCross: false}, //40
+    Opcode{mnemonic: EOR, mode: IndexedIndirect, bytes: 2, cycles: 6, pageCross: false}, //41
+    Opcode{mnemonic: JAM, mode: Implied, bytes: 1, cycles: 0, pageCross: false}, //42
+    Opcode{mnemonic: SRE, mode: IndexedIndirect, bytes: 2, cycles: 8, pageCross: false}, //43
+    Opcode{mnemonic: NOP, mode: ZeroPage, bytes: 2, cycles: 3, pageCross: false}, //44
+    Opcode{mnemonic: EOR, mode: ZeroPage, bytes: 2, cycles: 3, pageCross: false}, //45
+    Opcode{mnemonic: LSR, mode: ZeroPage, bytes: 2, cycles: 5, pageCross: false}, //46
+    Opcode{mnemonic: SRE, mode: ZeroPage, bytes: 2, cycles: 5, pageCross: false}, //47
+    Opcode{mnemonic: PHA, mode: Implied, bytes: 1, cycles: 3, pageCross: false}, //48
+    Opcode{mnemonic: EOR, mode: Immediate, bytes: 2, cycles: 2, pageCross: false}, //49
+    Opcode{mnemonic: LSR, mode: Accumulator, bytes: 1, cycles: 2, pageCross: false}, //4A
+    Opcode{mnemonic: ALR, mode: Immediate, bytes: 2, cycles: 2, pageCross: false}, //4B
+    Opcode{mnemonic: JMP, mode: Absolute, bytes: 3, cycles: 3, pageCross: false}, //4C
+    Opcode{mnemonic: EOR, mode: Absolute, bytes: 3, cycles: 4, pageCross: false}, //4D
+    Opcode{mnemonic: LSR, mode: Absolute, bytes: 3, cycles: 6, pageCross: false}, //4E
+    Opcode{mnemonic: SRE, mode: Absolute, bytes: 3, cycles: 6, pageCross: false}, //4F
+    Opcode{mnemonic: BVC, mode: Relative, bytes: 2, cycles: 2, pageCross: true}, //50
+    Opcode{mnemonic: EOR, mode: IndirectIndexed, bytes: 2, cycles: 5, pageCross: true}, //51
+    Opcode{mnemonic: JAM, mode: Implied, bytes: 1, cycles: 0, pageCross: false}, //52
+    Opcode{mnemonic: SRE, mode: IndirectIndexed, bytes: 2, cycles: 8, pageCross: false}, //53
+    Opcode{mnemonic: NOP, mode: ZeroPageX, bytes: 2, cycles: 4, pageCross: false}, //54
+    Opcode{mnemonic: EOR, mode: ZeroPageX, bytes: 2, cycles: 4, pageCross: false}, //55
+    Opcode{mnemonic: LSR, mode: ZeroPageX, bytes: 2, cycles: 6, pageCross: false}, //56
+    Opcode{mnemonic: SRE, mode: ZeroPageX, bytes: 2, cycles: 6, pageCross: false}, //57
+    Opcode{mnemonic: CLI, mode: Implied, bytes: 1, cycles: 2, pageCross: false}, //58
+    Opcode{mnemonic: EOR, mode: AbsoluteY, bytes: 3, cycles: 4, pageCross: true}, //59
+    Opcode{mnemonic: NOP, mode: Implied, bytes: 1, cycles: 2, pageCross: false}, //5A
+    Opcode{mnemonic: SRE, mode: AbsoluteY, bytes: 3, cycles: 7, pageCross: false}, //5B
+    Opcode{mnemonic: NOP, mode: AbsoluteX, bytes: 3, cycles: 4, pageCross: true}, //5C
+    Opcode{mnemonic: EOR, mode: AbsoluteX, bytes: 3, cycles: 4, pageCross: true}, //5D
+    Opcode{mnemonic: LSR, mode: AbsoluteX, bytes: 3, cycles: 7, pageCross: false}, //5E
+    Opcode{mnemonic: SRE, mode: AbsoluteX, bytes: 3, cycles: 7, pageCross: false}, //5F
+    Opcode{mnemonic: RTS, mode: Implied, bytes: 1, cycles: 6, pageCross: false}, //60
+    Opcode{mnemonic: ADC, mode: IndexedIndirect, bytes: 2, cycles: 6, pageCross: false}, //61
+    Opcode{mnemonic: JAM, mode: Implied, bytes: 1, cycles: 0, pageCross: false}, //62
+    Opcode{mnemonic: RRA, mode: IndexedIndirect, bytes: 2, cycles: 8, pageCross: false}, //63
+    Opcode{mnemonic: NOP, mode: ZeroPage, bytes: 2, cycles: 3, pageCross: false}, //64
+    Opcode{mnemonic: ADC, mode: ZeroPage, bytes: 2, cycles: 3, pageCross: false}, //65
+    Opcode{mnemonic: ROR, mode: ZeroPage, bytes: 2, cycles: 5, pageCross: false}, //66
+    Opcode{mnemonic: RRA, mode: ZeroPage, bytes: 2, cycles: 5, pageCross: false}, //67
+    Opcode{mnemonic: PLA, mode: Implied, bytes: 1, cycles: 4, pageCross: false}, //68
+    Opcode{mnemonic: ADC, mode: Immediate, bytes: 2, cycles: 2, pageCross: false}, //69
+    Opcode{mnemonic: ROR, mode: Accumulator, bytes: 1, cycles: 2, pageCross: false}, //6A
+    Opcode{mnemonic: ARR, mode: Immediate, bytes: 2, cycles: 2, pageCross: false}, //6B
+    Opcode{mnemonic: JMP, mode: Indirect, bytes: 3, cycles: 5, pageCross: false}, //6C
+    Opcode{mnemonic: ADC, mode: Absolute, bytes: 3, cycles: 4, pageCross: false}, //6D
+    Opcode{mnemonic: ROR, mode: Absolute, bytes: 3, cycles: 6, pageCross: false}, //6E
+    Opcode{mnemonic: RRA, mode: Absolute, bytes: 3, cycles: 6, pageCross: false}, //6F
+    Opcode{mnemonic: BVS, mode: Relative, bytes: 2, cycles: 2, pageCross: true}, //70
+    Opcode{mnemonic: ADC, mode: IndirectIndexed, bytes: 2, cycles: 5, pageCross: true}, //71
+    Opcode{mnemonic: JAM, mode: Implied, bytes: 1, cycles: 0, pageCross: false}, //72
+    Opcode{mnemonic: RRA, mode: IndirectIndexed, bytes: 2, cycles: 8, pageCross: false}, //73
+    Opcode{mnemonic: NOP, mode: ZeroPageX, bytes: 2, cycles: 4, pageCross: false}, //74
+    Opcode{mnemonic: ADC, mode: ZeroPageX, bytes: 2, cycles: 4, pageCross: false}, //75
+    Opcode{mnemonic: ROR, mode: ZeroPageX, bytes: 2, cycles: 6, pageCross: false}, //76
+    Opcode{mnemonic: RRA, mode: ZeroPageX, bytes: 2, cycles: 6, pageCross: false}, //77
+    Opcode{mnemonic: SEI, mode: Implied, bytes: 1, cycles: 2, pageCross: false}, //78
+    Opcode{mnemonic: ADC, mode: AbsoluteY, bytes: 3, cycles: 4, pageCross: true}, //79
+    Opcode{mnemonic: NOP, mode: Implied, bytes: 1, cycles: 2, pageCross: false}, //7A
+    Opcode{mnemonic: RRA, mode: AbsoluteY, bytes: 3, cycles: 7, pageCross: false}, //7B
+    Opcode{mnemonic: NOP, mode: AbsoluteX, bytes: 3, cycles: 4, pageCross: true}, //7C
+    Opcode{mnemonic: ADC, mode: AbsoluteX, bytes: 3, cycles: 4, pageCross: true}, //7D
+    Opcode{mnemonic: ROR, mode: AbsoluteX, bytes: 3, cycles: 7, pageCross: false}, //7E
+    Opcode{mnemonic: RRA, mode: AbsoluteX, bytes: 3, cycles: 7, pageCross: false}, //7F
+    Opcode{mnemonic: NOP, mode: Immediate, bytes: 2, cycles: 2, pageCross: false}, //80
+    Opcode{mnemonic: STA, mode: IndexedIndirect, bytes: 2, cycles: 6, pageCross: false}, //81
+    Opcode{mnemonic: NOP, mode: Immediate, bytes: 2, cycles: 2, pageCross: false}, //82
+    Opcode{mnemonic: SAX, mode: IndexedIndirect, bytes: 2, cycles: 6, pageCross: false}, //83
+    Opcode{mnemonic: STY, mode: ZeroPage, bytes: 2, cycles: 3, pageCross: false}, //84
+    Opcode{mnemonic: STA, mode: ZeroPage, bytes: 2, cycles: 3, pageCross: false}, //85
+    Opcode{mnemonic: STX, mode: ZeroPage, bytes: 2, cycles: 3, pageCross: false}, //86
+    Opcode{mnemonic: SAX, mode: ZeroPage, bytes: 2, cycles: 3, pageCross: false}, //87
+    Opcode{mnemonic: DEY, mode: Implied, bytes: 1, cycles: 2, pageCross: false}, //88
+    Opcode{mnemonic: NOP, mode: Immediate, bytes: 2, cycles: 2, pageCross: false}, //89
+    Opcode{mnemonic: TXA, mode: Implied, bytes: 1, cycles: 2, pageCross: false}, //8A
+    Opcode{mnemonic: ANE, mode: Immediate, bytes: 2, cycles: 2, pageCross: false}, //8B
+    Opcode{mnemonic: STY, mode: Absolute, bytes: 3, cycles: 4, pageCross: false}, //8C
+    Opcode{mnemonic: STA, mode: Absolute, bytes: 3, cycles: 4, pageCross: false}, //8D
+    Opcode{mnemonic: STX, mode: Absolute, bytes: 3, cycles: 4, pageCross: false}, //8E
+    Opcode{mnemonic: SAX, mode: Absolute, bytes: 3, cycles: 4, pageCross: false}, //8F
+    Opcode{mnemonic: BCC, mode: Relative, bytes: 2, cycles: 2, pageCross: true}, //90
+    Opcode{mnemonic: STA, mode: IndirectIndexed, bytes: 2, cycles: 6, pageCross: false}, //91
+    Opcode{mnemonic: JAM, mode: Implied, bytes: 1, cycles: 0, pageCross: false}, //92
+    Opcode{mnemonic: SHA, mode: IndirectIndexed, bytes: 2, cycles: 6, pageCross: false}, //93
+    Opcode{mnemonic: STY, mode: ZeroPageX, bytes: 2, cycles: 4, pageCross: false}, //94
+    Opcode{mnemonic: STA, mode: ZeroPageX, bytes: 2, cycles: 4, pageCross: false}, //95
+    Opcode{mnemonic: STX, mode: ZeroPageY, bytes: 2, cycles: 4, pageCross: false}, //96
+    Opcode{mnemonic: SAX, mode: ZeroPageY, bytes: 2, cycles: 4, pageCross: false}, //97
+    Opcode{mnemonic: TYA, mode: Implied, bytes: 1, cycles: 2, pageCross: false}, //98
+    Opcode{mnemonic: STA, mode: AbsoluteY, bytes: 3, cycles: 5, pageCross: false}, //99
+    Opcode{mnemonic: TXS, mode: Implied, bytes: 1, cycles: 2, pageCross: false}, //9A
+    Opcode{mnemonic: TAS, mode: AbsoluteY, bytes: 3, cycles: 5, pageCross: false}, //9B
+    Opcode{mnemonic: SHY, mode: AbsoluteX, bytes: 3, cycles: 5, pageCross: false}, //9C
+    Opcode{mnemonic: STA, mode: AbsoluteX, bytes: 3, cycles: 5, pageCross: false}, //9D
+    Opcode{mnemonic: SHX, mode: AbsoluteY, bytes: 3, cycles: 5, pageCross: false}, //9E
+    Opcode{mnemonic: SHA, mode: AbsoluteY, bytes: 3, cycles: 5, pageCross: false}, //9F
+    Opcode{mnemonic: LDY, mode: Immediate, bytes: 2, cycles: 2, pageCross: false}, //A0
+    Opcode{mnemonic: LDA, mode: IndexedIndirect, bytes: 2, cycles: 6, pageCross: false}, //A1
+    Opcode{mnemonic: LDX, mode: Immediate, bytes: 2, cycles: 2, pageCross: false}, //A2
+    Opcode{mnemonic: LAX, mode: IndexedIndirect, bytes: 2, cycles: 6, pageCross: false}, //A3
+    Opcode{mnemonic: LDY, mode: ZeroPage, bytes: 2, cycles: 3, pageCross: false}, //A4
+    Opcode{mnemonic: LDA, mode: ZeroPage, bytes: 2, cycles: 3, pageCross: false}, //A5
+    Opcode{mnemonic: LDX, mode: ZeroPage, bytes: 2, cycles: 3, pageCross: false}, //A6
+    Opcode{mnemonic: LAX, mode: ZeroPage, bytes: 2, cycles: 3, pageCross: false}, //A7
+    Opcode{mnemonic: TAY, mode: Implied, bytes: 1, cycles: 2, pageCross: false}, //A8
+    Opcode{mnemonic: LDA, mode: Immediate, bytes: 2, cycles: 2, pageCross: false}, //A9
+    Opcode{mnemonic: TAX, mode: Implied, bytes: 1, cycles: 2, pageCross: false}, //AA
+    Opcode{mnemonic: LXA, mode: Immediate, bytes: 2, cycles: 2, pageCross: false}, //AB
+    Opcode{mnemonic: LDY, mode: Absolute, bytes: 3, cycles: 4, pageCross: false}, //AC
+    Opcode{mnemonic: LDA, mode: Absolute, bytes: 3, cycles: 4, pageCross: false}, //AD
+    Opcode{mnemonic: LDX, mode: Absolute, bytes: 3, cycles: 4, pageCross: false}, //AE
+    Opcode{mnemonic: LAX, mode: Absolute, bytes: 3, cycles: 4, pageCross: false}, //AF
+    Opcode{mnemonic: BCS, mode: Relative, bytes: 2, cycles: 2, pageCross: true}, //B0
+    Opcode{mnemonic: LDA, mode: IndirectIndexed, bytes: 2, cycles: 5, pageCross: true}, //B1
+    Opcode{mnemonic: JAM, mode: Implied, bytes: 1, cycles: 0, pageCross: false}, //B2
+    Opcode{mnemonic: LAX, mode: IndirectIndexed, bytes: 2, cycles: 5, pageCross: true}, //B3
+    Opcode{mnemonic: LDY, mode: ZeroPageX, bytes: 2, cycles: 4, pageCross: false}, //B4
+    Opcode{mnemonic: LDA, mode: ZeroPageX, bytes: 2, cycles: 4, pageCross: false}, //B5
+    Opcode{mnemonic: LDX, mode: ZeroPageY, bytes: 2, cycles: 4, pageCross: false}, //B6
+    Opcode{mnemonic: LAX, mode: ZeroPageY, bytes: 2, cycles: 4, pageCross: false}, //B7
+    Opcode{mnemonic: CLV, mode: Implied, bytes: 1, cycles: 2, pageCross: false}, //B8
+    Opcode{mnemonic: LDA, mode: AbsoluteY, bytes: 3, cycles: 4, pageCross: true}, //B9
+    Opcode{mnemonic: TSX, mode: Implied, bytes: 1, cycles: 2, pageCross: false}, //BA
+    Opcode{mnemonic: LAS, mode: AbsoluteY, bytes: 3, cycles: 4, pageCross: true}, //BB
+    Opcode{mnemonic: LDY, mode: AbsoluteX, bytes: 3, cycles: 4, pageCross: true}, //BC
+    Opcode{mnemonic: LDA, mode: AbsoluteX, bytes: 3, cycles: 4, pageCross: true}, //BD
+    Opcode{mnemonic: LDX, mode: AbsoluteY, bytes: 3, cycles: 4, pageCross: true}, //BE
+    Opcode{mnemonic: LAX, mode: AbsoluteY, bytes: 3, cycles: 4, pageCross: true}, //BF
+    Opcode{mnemonic: CPY, mode: Immediate, bytes: 2, cycles: 2, pageCross: false}, //C0
+    Opcode{mnemonic: CMP, mode: IndexedIndirect, bytes: 2, cycles: 6, pageCross: false}, //C1
+    Opcode{mnemonic: NOP, mode: Immediate, bytes: 2, cycles: 2, pageCross: false}, //C2
+    Opcode{mnemonic: DCP, mode: IndexedIndirect, bytes: 2, cycles: 8, pageCross: false}, //C3
+    Opcode{mnemonic: CPY, mode: ZeroPage, bytes: 2, cycles: 3, pageCross: false}, //C4
+    Opcode{mnemonic: CMP, mode: ZeroPage, bytes: 2, cycles: 3, pageCross: false}, //C5
+    Opcode{mnemonic: DEC, mode: ZeroPage, bytes: 2, cycles: 5, pageCross: false}, //C6
+    Opcode{mnemonic: DCP, mode: ZeroPage, bytes: 2, cycles: 5, pageCross: false}, //C7
+    Opcode{mnemonic: INY, mode: Implied, bytes: 1, cycles: 2, pageCross: false}, //C8
+    Opcode{mnemonic: CMP, mode: Immediate, bytes: 2, cycles: 2, pageCross: false}, //C9
+    Opcode{mnemonic: DEX, mode: Implied, bytes: 1, cycles: 2, pageCross: false}, //CA
+    Opcode{mnemonic: AXS, mode: Immediate, bytes: 2, cycles: 2, pageCross: false}, //CB
+    Opcode{mnemonic: CPY, mode: Absolute, bytes: 3, cycles: 4, pageCross: false}, //CC
+    Opcode{mnemonic: CMP, mode: Absolute, bytes: 3, cycles: 4, pageCross: false}, //CD
+    Opcode{mnemonic: DEC, mode: Absolute, bytes: 3, cycles: 6, pageCross: false}, //CE
+    Opcode{mnemonic: DCP, mode: Absolute, bytes: 3, cycles: 6, pageCross: false}, //CF
+    Opcode{mnemonic: BNE, mode: Relative, bytes: 2, cycles: 2, pageCross: true}, //D0
+    Opcode{mnemonic: CMP, mode: IndirectIndexed, bytes: 2, cycles: 5, pageCross: true}, //D1
+    Opcode{mnemonic: JAM, mode: Implied, bytes: 1, cycles: 0, pageCross: false}, //D2
+    Opcode{mnemonic: DCP, mode: IndirectIndexed, bytes: 2, cycles: 8, pageCross: false}, //D3
+    Opcode{mnemonic: NOP, mode: ZeroPageX, bytes: 2, cycles: 4, pageCross: false}, //D4
+    Opcode{mnemonic: CMP, mode: ZeroPageX, bytes: 2, cycles: 4, pageCross: false}, //D5
+    Opcode{mnemonic: DEC, mode: ZeroPageX, bytes: 2, cycles: 6, pageCross: false}, //D6
+    Opcode{mnemonic: DCP, mode: ZeroPageX, bytes: 2, cycles: 6, pageCross: false}, //D7
+    Opcode{mnemonic: CLD, mode: Implied, bytes: 1, cycles: 2, pageCross: false}, //D8
+    Opcode{mnemonic: CMP, mode: AbsoluteY, bytes: 3, cycles: 4, pageCross: true}, //D9
+    Opcode{mnemonic: NOP, mode: Implied, bytes: 1, cycles: 2, pageCross: false}, //DA
+    Opcode{mnemonic: DCP, mode: AbsoluteY, bytes: 3, cycles: 7, pageCross: false}, //DB
+    Opcode{mnemonic: NOP, mode: AbsoluteX, bytes: 3, cycles: 4, pageCross: true}, //DC
+    Opcode{mnemonic: CMP, mode: AbsoluteX, bytes: 3, cycles: 4, pageCross: true}, //DD
+    Opcode{mnemonic: DEC, mode: AbsoluteX, bytes: 3, cycles: 7, pageCross: false}, //DE
+    Opcode{mnemonic: DCP, mode: AbsoluteX, bytes: 3, cycles: 7, pageCross: false}, //DF
+    Opcode{mnemonic: CPX, mode: Immediate, bytes: 2, cycles: 2, pageCross: false}, //E0
+    Opcode{mnemonic: SBC, mode: IndexedIndirect, bytes: 2, cycles: 6, pageCross: false}, //E1
+    Opcode{mnemonic: NOP, mode: Immediate, bytes: 2, cycles: 2, pageCross: false}, //E2
+    Opcode{mnemonic: ISC, mode: IndexedIndirect, bytes: 2, cycles: 8, pageCross: false}, //E3
+    Opcode{mnemonic: CPX, mode: ZeroPage, bytes: 2, cycles: 3, pageCross: false}, //E4
+    Opcode{mnemonic: SBC, mode: ZeroPage, bytes: 2, cycles: 3, pageCross: false}, //E5
+    Opcode{mnemonic: INC, mode: ZeroPage, bytes: 2, cycles: 5, pageCross: false}, //E6
+    Opcode{mnemonic: ISC, mode: ZeroPage, bytes: 2, cycles: 5, pageCross: false}, //E7
+    Opcode{mnemonic: INX, mode: Implied, bytes: 1, cycles: 2, pageCross: false}, //E8
+    Opcode{mnemonic: SBC, mode: Immediate, bytes: 2, cycles: 2, pageCross: false}, //E9
+    Opcode{mnemonic: NOP, mode: Implied, bytes: 1, cycles: 2, pageCross: false}, //EA
+    Opcode{mnemonic: SBC, mode: Immediate, bytes: 2, cycles: 2, pageCross: false}, //EB
+    Opcode{mnemonic: CPX, mode: Absolute, bytes: 3, cycles: 4, pageCross: false}, //EC
+    Opcode{mnemonic: SBC, mode: Absolute, bytes: 3, cycles: 4, pageCross: false}, //ED
+    Opcode{mnemonic: INC, mode: Absolute, bytes: 3, cycles: 6, pageCross: false}, //EE
+    Opcode{mnemonic: ISC, mode: Absolute, bytes: 3, cycles: 6, pageCross: false}, //EF
+    Opcode{mnemonic: BEQ, mode: Relative, bytes: 2, cycles: 2, pageCross: true}, //F0
+    Opcode{mnemonic: SBC, mode: IndirectIndexed, bytes: 2, cycles: 5, pageCross: true}, //F1
+    Opcode{mnemonic: JAM, mode: Implied, bytes: 1, cycles: 0, pageCross: false}, //F2
+    Opcode{mnemonic: ISC, mode: IndirectIndexed, bytes: 2, cycles: 8, pageCross: false}, //F3
+    Opcode{mnemonic: NOP, mode: ZeroPageX, bytes: 2, cycles: 4, pageCross: false}, //F4
+    Opcode{mnemonic: SBC, mode: ZeroPageX, bytes: 2, cycles: 4, pageCross: false}, //F5
+    Opcode{mnemonic: INC, mode: ZeroPageX, bytes: 2, cycles: 6, pageCross: false}, //F6
+    Opcode{mnemonic: ISC, mode: ZeroPageX, bytes: 2, cycles: 6, pageCross: false}, //F7
+    Opcode{mnemonic: SED, mode: Implied, bytes: 1, cycles: 2, pageCross: false}, //F8
+    Opcode{mnemonic: SBC, mode: AbsoluteY, bytes: 3, cycles: 4, pageCross: true}, //F9
+    Opcode{mnemonic: NOP, mode: Implied, bytes: 1, cycles: 2, pageCross: false}, //FA
+    Opcode{mnemonic: ISC, mode: AbsoluteY, bytes: 3, cycles: 7, pageCross: false}, //FB
+    Opcode{mnemonic: NOP, mode: AbsoluteX, bytes: 3, cycles: 4, pageCross: true}, //FC
+    Opcode{mnemonic: SBC, mode: AbsoluteX, bytes: 3, cycles: 4, pageCross: true}, //FD
+    Opcode{mnemonic: INC, mode: AbsoluteX, bytes: 3, cycles: 7, pageCross: false}, //FE
+    Opcode{mnemonic: ISC, mode: AbsoluteX, bytes: 3, cycles: 7, pageCross: false}, //FF
+];
+
+//formats the instruction whose bytes start at pc, bytes past its length are ignored
+pub fn disassemble(pc: u16, bytes: [u8; 3]) -> String{
+    let opcode = &OPCODES[bytes[0] as usize];
+    let byte = bytes[1];
+    let word = ((bytes[2] as u16)<<8) | bytes[1] as u16;
+    match opcode.mode{
+        Implied => format!("{}", opcode.mnemonic),
+        Accumulator => format!("{} A", opcode.mnemonic),
+        Immediate => format!("{} #${byte:02X}", opcode.mnemonic),
+        ZeroPage => format!("{} ${byte:02X}", opcode.mnemonic),
+        ZeroPageX => format!("{} ${byte:02X},X", opcode.mnemonic),
+        ZeroPageY => format!("{} ${byte:02X},Y", opcode.mnemonic),
+        Absolute => format!("{} ${word:04X}", opcode.mnemonic),
+        AbsoluteX => format!("{} ${word:04X},X", opcode.mnemonic),
+        AbsoluteY => format!("{} ${word:04X},Y", opcode.mnemonic),
+        Indirect => format!("{} (${word:04X})", opcode.mnemonic),
+        IndexedIndirect => format!("{} (${byte:02X},X)", opcode.mnemonic),
+        IndirectIndexed => format!("{} (${byte:02X}),Y", opcode.mnemonic),
+        Relative => format!("{} ${:04X}", opcode.mnemonic, pc.wrapping_add(2).wrapping_add(byte as i8 as u16)),
+    }
+}
@@ -1,22 +1,756 @@
+use crate::Cartridge_NES::Cartridge;
 
-use crate::Mapper;
+const CIRAM_SIZE: usize = 0x800;
+const OAM_SIZE: usize = 0x100;
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 240;
+const DOTS: u16 = 341;
+const SCANLINES: u16 = 262;
+const VBLANK_LINE: u16 = 241;
+const PRE_RENDER_LINE: u16 = 261;
+const SPRITES_PER_LINE: usize = 8;
+
+//PPUCTRL
+const CTRL_INCREMENT: u8 = 0x04;
+const CTRL_SPRITE_TABLE: u8 = 0x08;
+const CTRL_BACKGROUND_TABLE: u8 = 0x10;
+const CTRL_SPRITE_SIZE: u8 = 0x20;
+const CTRL_NMI: u8 = 0x80;
+//PPUMASK
+const MASK_GRAYSCALE: u8 = 0x01;
+const MASK_BACKGROUND_LEFT: u8 = 0x02;
+const MASK_SPRITES_LEFT: u8 = 0x04;
+const MASK_BACKGROUND: u8 = 0x08;
+const MASK_SPRITES: u8 = 0x10;
+//PPUSTATUS
+const STATUS_OVERFLOW: u8 = 0x20;
+const STATUS_SPRITE_ZERO: u8 = 0x40;
+const STATUS_VBLANK: u8 = 0x80;
+//sprite attributes
+const SPRITE_PALETTE: u8 = 0x03;
+const SPRITE_BEHIND: u8 = 0x20;
+const SPRITE_FLIP_X: u8 = 0x40;
+const SPRITE_FLIP_Y: u8 = 0x80;
+
+//how the picture is produced, both backends keep the registers, vblank and NMI timing dot exact.
+//Test ROM results: none recorded yet, nestest, blargg ppu_vbl_nmi, sprite_hit_tests and mmc3_test
+//haven't been run on either backend. To run them (add --renderer scanline for the other one):
+//  nestest: nes --start 0xC000 --trace --frames 1 nestest.nes, compare with nestest.log up to $C66E
+//  ppu_vbl_nmi, mmc3_test_2: the run ends on the $6000 result, exit status 0 when it passed
+//  sprite_hit_tests_2005, the original mmc3_test: only show their result on screen
+//The Console_NES tests check that both draw identical frames and report sprite 0 hit and
+//MMC3 IRQs on the same scanline.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Renderer{
+    //dot by dot with the real fetch timing, mid-scanline register writes take effect on the right pixel
+    Dot,
+    //a whole scanline on its first dot: scroll, palette or pattern changes during a line only show
+    //on the next one, sprite 0 hit is found then but reported on the dot of the pixel, sprite overflow
+    //is set at dot 257, and the sprite fetches still happen at dot 257 so MMC3 IRQs get one clock per line
+    Scanline,
+}
+
+#[derive(Clone)]
 pub struct PPU{
+    renderer: Renderer,
+    ctrl: u8,
+    mask: u8,
+    status: u8, //only the top 3 bits exist
+    suppressVblank: bool, //$2002 was read just before the flag was set
+    oamAddr: u8,
+    oam: Vec<u8>,
+    
+    //loopy registers: v and t are yyy NN YYYYY XXXXX (fine Y, nametable, coarse Y, coarse X)
+    v: u16,     //current VRAM address
+    t: u16,     //temporary VRAM address, the top left of the screen
+    x: u8,      //fine X scroll
+    w: bool,    //first or second write toggle of $2005/$2006
+    readBuffer: u8, //$2007 reads return the previous read
+    openBus: u8,    //last value written to a register, what the write-only registers read back
+    
+    ciram: Vec<u8>,     //the 2KB of nametable RAM in the console, arranged by the cartridge mirroring
+    palette: [u8; 32],  //6 bit colors
+    
+    //background pipeline: the next tile is fetched over 8 dots while the shifters output the current two
+    tileIndex: u8,
+    tileAttribute: u8,
+    tileLow: u8,
+    tileHigh: u8,
+    patternLow: u16,
+    patternHigh: u16,
+    attributeLow: u16,
+    attributeHigh: u16,
+    
+    //sprite evaluation for the next scanline, one OAM read or secondary OAM write per dot
+    secondaryOAM: [u8; 32],
+    oamLatch: u8,
+    evalSprite: u8,     //n, sprite being looked at
+    evalByte: u8,       //m, byte of that sprite
+    secondaryIndex: usize,
+    evalDone: bool,
+    spriteZeroNext: bool,
+    
+    //sprites of the current scanline, fetched during dots 257-320 of the previous one
+    spriteCount: usize,
+    spriteLow: [u8; SPRITES_PER_LINE],
+    spriteHigh: [u8; SPRITES_PER_LINE],
+    spriteAttribute: [u8; SPRITES_PER_LINE],
+    spriteX: [u8; SPRITES_PER_LINE],
+    spriteZeroOnLine: bool,
+    spriteZeroHitDot: Option<u16>, //scanline renderer: where the hit found when drawing the line happens
+    
+    dot: u16,
+    scanline: u16,
+    frame: u64,
+    framebuffer: Vec<u16>, //6 bit palette index, bits 6-8 are the emphasis bits of PPUMASK (red, green, blue on NTSC)
 }
 
 impl PPU{
     pub fn new() -> Self{
-        PPU{ }
+        PPU{
+            renderer: Renderer::Dot,
+            ctrl: 0,
+            mask: 0,
+            status: 0,
+            suppressVblank: false,
+            oamAddr: 0,
+            oam: vec![0; OAM_SIZE],
+            v: 0,
+            t: 0,
+            x: 0,
+            w: false,
+            readBuffer: 0,
+            openBus: 0,
+            ciram: vec![0; CIRAM_SIZE],
+            palette: [0; 32],
+            tileIndex: 0,
+            tileAttribute: 0,
+            tileLow: 0,
+            tileHigh: 0,
+            patternLow: 0,
+            patternHigh: 0,
+            attributeLow: 0,
+            attributeHigh: 0,
+            secondaryOAM: [0xFF; 32],
+            oamLatch: 0,
+            evalSprite: 0,
+            evalByte: 0,
+            secondaryIndex: 0,
+            evalDone: false,
+            spriteZeroNext: false,
+            spriteCount: 0,
+            spriteLow: [0; SPRITES_PER_LINE],
+            spriteHigh: [0; SPRITES_PER_LINE],
+            spriteAttribute: [0; SPRITES_PER_LINE],
+            spriteX: [0; SPRITES_PER_LINE],
+            spriteZeroOnLine: false,
+            spriteZeroHitDot: None,
+            dot: 0,
+            scanline: 0,
+            frame: 0,
+            framebuffer: vec![0; SCREEN_WIDTH*SCREEN_HEIGHT],
+        }
     }
-}
-
-impl Mapper for PPU{
-    fn read(&self, adr: usize) -> u8{
+    
+    //the reset line clears the write registers, the toggle and the read buffer but not the memory or v
+    pub fn reset(&mut self){
+        self.ctrl = 0;
+        self.mask = 0;
+        self.t = 0;
+        self.x = 0;
+        self.w = false;
+        self.readBuffer = 0;
+        self.dot = 0;
+        self.scanline = 0;
+    }
+    
+    pub fn read(&mut self, adr: u16, cart: &mut Cartridge) -> u8{
+        let data = match adr{
+            0x2002 => {
+                let data = self.status | (self.openBus & 0x1F);
+                self.status &= !STATUS_VBLANK;
+                self.w = false;
+                //a read on the dot before the flag goes up sees it clear and cancels it for this frame,
+                //a read right after clears it before the CPU notices the NMI
+                if self.scanline == VBLANK_LINE && self.dot == 1{
+                    self.suppressVblank = true;
+                }
+                data
+            }
+            //the secondary OAM clear forces the bus to $FF
+            0x2004 if self.renderingLine() && (1..=64).contains(&self.dot) => 0xFF,
+            0x2004 => self.oam[self.oamAddr as usize],
+            0x2007 => {
+                let adr = self.v & 0x3FFF;
+                let data = if adr >= 0x3F00{
+                    //palette reads skip the buffer, which gets the nametable byte underneath instead
+                    self.readBuffer = self.memoryRead(adr - 0x1000, cart);
+                    self.memoryRead(adr, cart) | (self.openBus & 0xC0)
+                }
+                else {
+                    let data = self.readBuffer;
+                    self.readBuffer = self.memoryRead(adr, cart);
+                    data
+                };
+                self.incrementV();
+                data
+            }
+            _ => self.openBus, //write only
+        };
+        self.openBus = data;
+        data
+    }
+    
+    pub fn write(&mut self, adr: u16, data: u8, cart: &mut Cartridge){
+        self.openBus = data;
         match adr{
-            0x2002 => 0xFF,
-            _ => {panic!("not handled {adr:#x}");}
+            0x2000 => {
+                self.ctrl = data;
+                self.t = (self.t & !0x0C00) | ((data as u16 & 0x03) << 10);
+            }
+            0x2001 => self.mask = data,
+            0x2002 => (),
+            0x2003 => self.oamAddr = data,
+            0x2004 => {
+                //bits 2-4 of the sprite attributes don't exist
+                let data = if self.oamAddr & 3 == 2 {data & 0xE3} else {data};
+                self.oam[self.oamAddr as usize] = data;
+                self.oamAddr = self.oamAddr.wrapping_add(1);
+            }
+            0x2005 => {
+                if !self.w{
+                    self.t = (self.t & !0x001F) | (data as u16 >> 3);
+                    self.x = data & 0x07;
+                }
+                else {
+                    self.t = (self.t & !0x73E0) | ((data as u16 & 0x07) << 12) | ((data as u16 & 0xF8) << 2);
+                }
+                self.w = !self.w;
+            }
+            0x2006 => {
+                if !self.w{
+                    self.t = (self.t & 0x00FF) | ((data as u16 & 0x3F) << 8);
+                }
+                else {
+                    self.t = (self.t & 0xFF00) | data as u16;
+                    self.v = self.t;
+                }
+                self.w = !self.w;
+            }
+            _ => {
+                self.memoryWrite(self.v & 0x3FFF, data, cart);
+                self.incrementV();
+            }
+        }
+    }
+    
+    //during rendering $2007 accesses bump v with both the coarse X and the Y increments
+    fn incrementV(&mut self){
+        if self.renderingLine(){
+            self.incrementX();
+            self.incrementY();
+            return;
+        }
+        let increment = if self.ctrl & CTRL_INCREMENT == 0 {1} else {32};
+        self.v = self.v.wrapping_add(increment) & 0x7FFF;
+    }
+    
+    //PPU address space:
+    //  $0000-$1FFF pattern tables, on the cartridge
+    //  $2000-$2FFF nametables, CIRAM or cartridge VRAM depending on the mirroring the mapper reports right now
+    //  $3000-$3EFF mirror of $2000-$2EFF
+    //  $3F00-$3FFF palette RAM, 32 bytes mirrored
+    fn memoryRead(&mut self, adr: u16, cart: &mut Cartridge) -> u8{
+        match adr{
+            0x0000..=0x1FFF => cart.ppuRead(adr, &self.ciram),
+            0x2000..=0x3EFF => cart.ppuRead(0x2000 + (adr & 0x0FFF), &self.ciram),
+            _ => self.paletteRead(adr) & self.grayscaleMask(),
+        }
+    }
+    
+    fn memoryWrite(&mut self, adr: u16, data: u8, cart: &mut Cartridge){
+        match adr{
+            0x0000..=0x1FFF => cart.ppuWrite(adr, data, &mut self.ciram),
+            0x2000..=0x3EFF => cart.ppuWrite(0x2000 + (adr & 0x0FFF), data, &mut self.ciram),
+            _ => self.palette[Self::paletteIndex(adr)] = data & 0x3F,
+        }
+    }
+    
+    //$3F10/$3F14/$3F18/$3F1C are the same bytes as $3F00/$3F04/$3F08/$3F0C
+    fn paletteIndex(adr: u16) -> usize{
+        let index = (adr & 0x1F) as usize;
+        if index & 0x13 == 0x10 {index & 0x0F} else {index}
+    }
+    
+    pub fn paletteRead(&self, adr: u16) -> u8{
+        self.palette[Self::paletteIndex(adr)]
+    }
+    
+    //grayscale keeps only the column $x0 of the palette, reads of the palette see it too
+    fn grayscaleMask(&self) -> u8{
+        if self.mask & MASK_GRAYSCALE != 0 {0x30} else {0x3F}
+    }
+    
+    //the NMI output is low (asserted) while both the vblank flag and the PPUCTRL enable are set,
+    //so turning the enable on during vblank makes another edge
+    pub fn nmi(&self) -> bool{
+        self.ctrl & CTRL_NMI != 0 && self.status & STATUS_VBLANK != 0
+    }
+    
+    fn renderingEnabled(&self) -> bool{
+        self.mask & (MASK_BACKGROUND | MASK_SPRITES) != 0
+    }
+    
+    //visible and pre-render lines with rendering on, where the PPU owns its bus
+    fn renderingLine(&self) -> bool{
+        self.renderingEnabled() && (self.scanline < SCREEN_HEIGHT as u16 || self.scanline == PRE_RENDER_LINE)
+    }
+    
+    //one dot, three per CPU cycle on NTSC
+    pub fn tick(&mut self, cart: &mut Cartridge){
+        let preRender = self.scanline == PRE_RENDER_LINE;
+        
+        if self.scanline == VBLANK_LINE && self.dot == 1{
+            if !self.suppressVblank{
+                self.status |= STATUS_VBLANK;
+            }
+            self.suppressVblank = false;
+        }
+        if preRender && self.dot == 1{
+            self.status &= !(STATUS_VBLANK | STATUS_SPRITE_ZERO | STATUS_OVERFLOW);
+        }
+        match self.renderer{
+            Renderer::Dot => self.dotStep(cart),
+            Renderer::Scanline => self.scanlineStep(cart),
         }
         
+        //the pre-render line of odd frames is one dot shorter when rendering
+        if preRender && self.dot == DOTS-2 && self.frame % 2 == 1 && self.renderingEnabled(){
+            self.dot += 1;
+        }
+        self.dot += 1;
+        if self.dot == DOTS{
+            self.dot = 0;
+            self.scanline += 1;
+            if self.scanline == SCANLINES{
+                self.scanline = 0;
+                self.frame += 1;
+            }
+        }
+    }
+    
+    fn dotStep(&mut self, cart: &mut Cartridge){
+        let visible = self.scanline < SCREEN_HEIGHT as u16;
+        if self.renderingLine(){
+            self.backgroundFetch(cart);
+            if visible{
+                self.spriteEvaluation(self.dot);
+            }
+            self.spriteFetch(cart);
+            if self.scanline == PRE_RENDER_LINE && (280..=304).contains(&self.dot){
+                self.copyY();
+            }
+        }
+        if visible && (1..=SCREEN_WIDTH as u16).contains(&self.dot){
+            let x = (self.dot - 1) as usize;
+            let (color, palette) = self.backgroundPixel();
+            if self.outputPixel(x, color, palette){
+                self.status |= STATUS_SPRITE_ZERO;
+            }
+        }
+    }
+    
+    //the line is drawn on its first dot from the scroll at that time, then only the updates of v
+    //and the sprite work are done, all on the dot where they end on the real PPU
+    fn scanlineStep(&mut self, cart: &mut Cartridge){
+        let visible = self.scanline < SCREEN_HEIGHT as u16;
+        if visible && self.dot == 1{
+            self.renderLine(cart);
+        }
+        if self.spriteZeroHitDot == Some(self.dot){
+            self.status |= STATUS_SPRITE_ZERO;
+            self.spriteZeroHitDot = None;
+        }
+        if !self.renderingLine(){
+            return;
+        }
+        match self.dot{
+            //the pre-render line fetches tiles like a visible one, a single read is enough to bring A12 low for the mappers
+            1 if self.scanline == PRE_RENDER_LINE => {
+                self.memoryRead(Self::nametableAdr(self.v), cart);
+            }
+            256 => self.incrementY(),
+            257 => {
+                self.copyX();
+                if visible{
+                    for dot in 1..=256{
+                        self.spriteEvaluation(dot);
+                    }
+                }
+                self.startSpriteFetch();
+                for slot in 0..SPRITES_PER_LINE{
+                    for phase in [0, 2, 4, 6]{
+                        self.fetchSprite(slot, phase, cart);
+                    }
+                }
+                self.oamAddr = 0;
+            }
+            280 if self.scanline == PRE_RENDER_LINE => self.copyY(),
+            _ => (),
+        }
+    }
+    
+    fn renderLine(&mut self, cart: &mut Cartridge){
+        let mut background = [(0u8, 0u8); SCREEN_WIDTH];
+        if self.renderingEnabled() && self.mask & MASK_BACKGROUND != 0{
+            let mut v = self.v;
+            //33 tiles cover the line whatever the fine X scroll
+            for tile in 0..=SCREEN_WIDTH/8{
+                let tileIndex = self.memoryRead(Self::nametableAdr(v), cart);
+                let attribute = self.memoryRead(Self::attributeAdr(v), cart);
+                let palette = (attribute >> Self::attributeShift(v)) & 3;
+                let adr = self.backgroundAdr(tileIndex, v);
+                let low = self.memoryRead(adr, cart);
+                let high = self.memoryRead(adr + 8, cart);
+                for bit in 0..8{
+                    let x = (tile*8 + bit) as isize - self.x as isize;
+                    if (0..SCREEN_WIDTH as isize).contains(&x){
+                        let color = ((high >> (7 - bit)) & 1) << 1 | ((low >> (7 - bit)) & 1);
+                        background[x as usize] = (color, palette);
+                    }
+                }
+                v = Self::incrementCoarseX(v);
+            }
+        }
+        for (x, (color, palette)) in background.into_iter().enumerate(){
+            if self.outputPixel(x, color, palette) && self.spriteZeroHitDot.is_none(){
+                self.spriteZeroHitDot = Some(x as u16 + 1);
+            }
+        }
+    }
+    
+    pub fn setRenderer(&mut self, renderer: Renderer){
+        self.renderer = renderer;
+        self.spriteZeroHitDot = None;
+    }
+    
+    pub fn renderer(&self) -> Renderer{
+        self.renderer
+    }
+    
+    fn backgroundFetch(&mut self, cart: &mut Cartridge){
+        match self.dot{
+            2..=257 | 321..=337 => {
+                self.shiftBackground();
+                match (self.dot-1) % 8{
+                    0 => {
+                        self.loadBackground();
+                        self.tileIndex = self.memoryRead(Self::nametableAdr(self.v), cart);
+                    }
+                    2 => {
+                        let attribute = self.memoryRead(Self::attributeAdr(self.v), cart);
+                        self.tileAttribute = (attribute >> Self::attributeShift(self.v)) & 3;
+                    }
+                    4 => self.tileLow = self.memoryRead(self.backgroundAdr(self.tileIndex, self.v), cart),
+                    6 => self.tileHigh = self.memoryRead(self.backgroundAdr(self.tileIndex, self.v) + 8, cart),
+                    7 => self.incrementX(),
+                    _ => (),
+                }
+                if self.dot == 256{
+                    self.incrementY();
+                }
+                if self.dot == 257{
+                    self.copyX();
+                }
+            }
+            //unused nametable fetches, MMC5 counts them
+            338 | 340 => {
+                self.memoryRead(Self::nametableAdr(self.v), cart);
+            }
+            _ => (),
+        }
+    }
+    
+    fn nametableAdr(v: u16) -> u16{
+        0x2000 | (v & 0x0FFF)
     }
-    fn write(&mut self, adr: usize,  data: u8){
+    
+    fn attributeAdr(v: u16) -> u16{
+        0x23C0 | (v & 0x0C00) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07)
     }
-}
\ No newline at end of file
+    
+    //quadrant of the 32x32 pixel block in the attribute byte
+    fn attributeShift(v: u16) -> u16{
+        ((v >> 4) & 4) | (v & 2)
+    }
+    
+    fn backgroundAdr(&self, tileIndex: u8, v: u16) -> u16{
+        let table = if self.ctrl & CTRL_BACKGROUND_TABLE == 0 {0x0000} else {0x1000};
+        table + tileIndex as u16 * 16 + (v >> 12)
+    }
+    
+    fn shiftBackground(&mut self){
+        self.patternLow <<= 1;
+        self.patternHigh <<= 1;
+        self.attributeLow <<= 1;
+        self.attributeHigh <<= 1;
+    }
+    
+    //the fetched tile goes in the low byte, it reaches the output after the current one
+    fn loadBackground(&mut self){
+        self.patternLow = (self.patternLow & 0xFF00) | self.tileLow as u16;
+        self.patternHigh = (self.patternHigh & 0xFF00) | self.tileHigh as u16;
+        self.attributeLow = (self.attributeLow & 0xFF00) | if self.tileAttribute & 1 != 0 {0xFF} else {0};
+        self.attributeHigh = (self.attributeHigh & 0xFF00) | if self.tileAttribute & 2 != 0 {0xFF} else {0};
+    }
+    
+    fn incrementX(&mut self){
+        self.v = Self::incrementCoarseX(self.v);
+    }
+    
+    fn incrementCoarseX(v: u16) -> u16{
+        if v & 0x001F == 31{
+            (v & !0x001F) ^ 0x0400 //next horizontal nametable
+        }
+        else {
+            v + 1
+        }
+    }
+    
+    fn incrementY(&mut self){
+        if self.v & 0x7000 != 0x7000{
+            self.v += 0x1000;
+            return;
+        }
+        self.v &= !0x7000;
+        let mut coarseY = (self.v & 0x03E0) >> 5;
+        if coarseY == 29{
+            coarseY = 0;
+            self.v ^= 0x0800; //next vertical nametable
+        }
+        else if coarseY == 31{
+            coarseY = 0; //rows 30 and 31 are the attribute table, no nametable switch
+        }
+        else {
+            coarseY += 1;
+        }
+        self.v = (self.v & !0x03E0) | (coarseY << 5);
+    }
+    
+    fn copyX(&mut self){
+        self.v = (self.v & !0x041F) | (self.t & 0x041F);
+    }
+    
+    fn copyY(&mut self){
+        self.v = (self.v & !0x7BE0) | (self.t & 0x7BE0);
+    }
+    
+    fn spriteHeight(&self) -> i16{
+        if self.ctrl & CTRL_SPRITE_SIZE == 0 {8} else {16}
+    }
+    
+    //sprites are compared against the current line and drawn on the next one, OAM Y is one less than the top
+    fn spriteInRange(&self, y: u8) -> bool{
+        let row = self.scanline as i16 - y as i16;
+        row >= 0 && row < self.spriteHeight()
+    }
+    
+    //dots 1-64 clear the secondary OAM, 65-256 read OAM on odd dots and write the secondary OAM on even ones
+    fn spriteEvaluation(&mut self, dot: u16){
+        match dot{
+            1..=64 if dot.is_multiple_of(2) => self.secondaryOAM[(dot/2 - 1) as usize] = 0xFF,
+            65..=256 => {
+                if dot == 65{
+                    self.evalSprite = 0;
+                    self.evalByte = 0;
+                    self.secondaryIndex = 0;
+                    self.evalDone = false;
+                    self.spriteZeroNext = false;
+                }
+                if dot % 2 == 1{
+                    self.oamLatch = self.oam[(self.evalSprite as usize)*4 + self.evalByte as usize];
+                }
+                else if !self.evalDone{
+                    self.evaluate();
+                }
+            }
+            _ => (),
+        }
+    }
+    
+    fn evaluate(&mut self){
+        if self.secondaryIndex < self.secondaryOAM.len(){
+            self.secondaryOAM[self.secondaryIndex] = self.oamLatch;
+            if self.evalByte == 0 && !self.spriteInRange(self.oamLatch){
+                self.nextSprite();
+                return;
+            }
+            if self.evalByte == 0 && self.evalSprite == 0{
+                self.spriteZeroNext = true;
+            }
+            self.secondaryIndex += 1;
+            self.evalByte = (self.evalByte + 1) % 4;
+            if self.evalByte == 0{
+                self.nextSprite();
+            }
+        }
+        //with 8 sprites found the PPU keeps looking for a 9th, but it also increments m when it
+        //should not, so it compares tile numbers, attributes and X positions as Y coordinates
+        else if self.spriteInRange(self.oamLatch){
+            self.status |= STATUS_OVERFLOW;
+            self.evalDone = true;
+        }
+        else {
+            self.evalByte = (self.evalByte + 1) % 4;
+            self.nextSprite();
+        }
+    }
+    
+    fn nextSprite(&mut self){
+        self.evalSprite += 1;
+        if self.evalSprite == 64{
+            self.evalSprite = 0;
+            self.evalDone = true;
+        }
+    }
+    
+    //dots 257-320: 8 dots per sprite slot, two garbage nametable fetches then the pattern bytes
+    fn spriteFetch(&mut self, cart: &mut Cartridge){
+        if !(257..=320).contains(&self.dot){
+            return;
+        }
+        self.oamAddr = 0;
+        if self.dot == 257{
+            self.startSpriteFetch();
+        }
+        self.fetchSprite(((self.dot - 257) / 8) as usize, (self.dot - 257) % 8, cart);
+    }
+    
+    fn startSpriteFetch(&mut self){
+        self.spriteCount = if self.scanline == PRE_RENDER_LINE {0} else {self.secondaryIndex / 4};
+        self.spriteZeroOnLine = self.spriteZeroNext && self.scanline != PRE_RENDER_LINE;
+    }
+    
+    fn fetchSprite(&mut self, slot: usize, phase: u16, cart: &mut Cartridge){
+        let sprite = &self.secondaryOAM[slot*4..slot*4 + 4];
+        let (y, tile, attribute, x) = (sprite[0], sprite[1], sprite[2], sprite[3]);
+        match phase{
+            0 => {
+                self.memoryRead(Self::nametableAdr(self.v), cart);
+                self.spriteAttribute[slot] = attribute;
+                self.spriteX[slot] = x;
+            }
+            2 => {
+                self.memoryRead(Self::nametableAdr(self.v), cart);
+            }
+            4 | 6 => {
+                let mut adr = self.spriteAdr(y, tile, attribute);
+                if phase == 6{
+                    adr += 8; //high plane
+                }
+                let mut data = self.memoryRead(adr, cart);
+                if slot >= self.spriteCount{
+                    data = 0; //unused slots fetch tile $FF but stay transparent
+                }
+                else if attribute & SPRITE_FLIP_X != 0{
+                    data = data.reverse_bits();
+                }
+                if phase == 4{
+                    self.spriteLow[slot] = data;
+                }
+                else {
+                    self.spriteHigh[slot] = data;
+                }
+            }
+            _ => (),
+        }
+    }
+    
+    fn spriteAdr(&self, y: u8, tile: u8, attribute: u8) -> u16{
+        let height = self.spriteHeight();
+        let mut row = (self.scanline as i16 - y as i16).rem_euclid(height);
+        if attribute & SPRITE_FLIP_Y != 0{
+            row = height - 1 - row;
+        }
+        let (table, tile) = if height == 8{
+            (if self.ctrl & CTRL_SPRITE_TABLE == 0 {0x0000} else {0x1000}, tile)
+        }
+        else {
+            //8x16 sprites take the table from bit 0 of the tile number, the bottom half is the next tile
+            ((tile as u16 & 1) * 0x1000, (tile & 0xFE) + (row / 8) as u8)
+        };
+        table + tile as u16 * 16 + (row % 8) as u16
+    }
+    
+    //color (0-3) and palette of the background at the current dot
+    fn backgroundPixel(&self) -> (u8, u8){
+        let bit = 0x8000 >> self.x;
+        let color = (((self.patternHigh & bit) != 0) as u8) << 1 | ((self.patternLow & bit) != 0) as u8;
+        let palette = (((self.attributeHigh & bit) != 0) as u8) << 1 | ((self.attributeLow & bit) != 0) as u8;
+        (color, palette)
+    }
+    
+    //color, palette, behind the background, is sprite 0: the first opaque sprite wins
+    fn spritePixel(&self, x: usize) -> Option<(u8, u8, bool, bool)>{
+        if self.mask & MASK_SPRITES == 0 || (x < 8 && self.mask & MASK_SPRITES_LEFT == 0){
+            return None;
+        }
+        for slot in 0..self.spriteCount{
+            let offset = x as i16 - self.spriteX[slot] as i16;
+            if !(0..8).contains(&offset){
+                continue;
+            }
+            let bit = 7 - offset;
+            let color = ((self.spriteHigh[slot] >> bit) & 1) << 1 | ((self.spriteLow[slot] >> bit) & 1);
+            if color != 0{
+                let attribute = self.spriteAttribute[slot];
+                return Some((color, 4 + (attribute & SPRITE_PALETTE), attribute & SPRITE_BEHIND != 0, slot == 0 && self.spriteZeroOnLine));
+            }
+        }
+        None
+    }
+    
+    //mixes the background pixel with the sprites and writes it, returns true on a sprite 0 hit
+    fn outputPixel(&mut self, x: usize, bgColor: u8, bgPalette: u8) -> bool{
+        let mut hit = false;
+        let adr = if !self.renderingEnabled(){
+            //with rendering off the backdrop color is shown, or the palette entry v points at
+            if self.v & 0x3F00 == 0x3F00 {self.v} else {0x3F00}
+        }
+        else {
+            let (bgColor, bgPalette) = if self.mask & MASK_BACKGROUND == 0 || (x < 8 && self.mask & MASK_BACKGROUND_LEFT == 0){
+                (0, 0)
+            } else {
+                (bgColor, bgPalette)
+            };
+            let (color, palette) = match self.spritePixel(x){
+                None => (bgColor, bgPalette),
+                Some((spColor, spPalette, behind, spriteZero)) => {
+                    hit = bgColor != 0 && spriteZero && x != 255;
+                    if bgColor != 0 && behind {(bgColor, bgPalette)} else {(spColor, spPalette)}
+                }
+            };
+            if color == 0 {0x3F00} else {0x3F00 + (palette*4 + color) as u16}
+        };
+        let color = self.paletteRead(adr) & self.grayscaleMask();
+        self.framebuffer[self.scanline as usize*SCREEN_WIDTH + x] = color as u16 | ((self.mask as u16 & 0xE0) << 1);
+        hit
+    }
+    
+    pub fn scanline(&self) -> u16{
+        self.scanline
+    }
+    
+    pub fn dot(&self) -> u16{
+        self.dot
+    }
+    
+    //number of frames started since power on
+    pub fn frame(&self) -> u64{
+        self.frame
+    }
+    
+    pub fn framebuffer(&self) -> &[u16]{
+        &self.framebuffer
+    }
+}
@@ -0,0 +1,152 @@
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+
+const COLORS: usize = 64;
+const EMPHASIS_COMBINATIONS: usize = 8;
+
+//a common NTSC 2C02 palette
+const DEFAULT_PALETTE: [[u8; 3]; COLORS] = [
+    [84, 84, 84], [0, 30, 116], [8, 16, 144], [48, 0, 136], [68, 0, 100], [92, 0, 48], [84, 4, 0], [60, 24, 0],
+    [32, 42, 0], [8, 58, 0], [0, 64, 0], [0, 60, 0], [0, 50, 60], [0, 0, 0], [0, 0, 0], [0, 0, 0],
+    [152, 150, 152], [8, 76, 196], [48, 50, 236], [92, 30, 228], [136, 20, 176], [160, 20, 100], [152, 34, 32], [120, 60, 0],
+    [84, 90, 0], [40, 114, 0], [8, 124, 0], [0, 118, 40], [0, 102, 120], [0, 0, 0], [0, 0, 0], [0, 0, 0],
+    [236, 238, 236], [76, 154, 236], [120, 124, 236], [176, 98, 236], [228, 84, 236], [236, 88, 180], [236, 106, 100], [212, 136, 32],
+    [160, 170, 0], [116, 196, 0], [76, 208, 32], [56, 204, 108], [56, 180, 204], [60, 60, 60], [0, 0, 0], [0, 0, 0],
+    [236, 238, 236], [168, 204, 236], [188, 188, 236], [212, 178, 236], [236, 174, 236], [236, 174, 212], [236, 180, 176], [228, 196, 144],
+    [204, 210, 120], [180, 222, 120], [168, 226, 144], [152, 226, 180], [160, 214, 228], [160, 162, 160], [0, 0, 0], [0, 0, 0],
+];
+
+#[derive(Debug)]
+pub enum PaletteError{
+    Io(std::io::Error),
+    BadSize(usize),
+}
+
+impl fmt::Display for PaletteError{
+    fn fmt(&self, f:&mut fmt::Formatter<'_>)->fmt::Result{
+        match self{
+            PaletteError::Io(err) => write!(f, "can't read palette file: {err}"),
+            PaletteError::BadSize(size) => write!(f, "palette file is {size} bytes, expected {} or {}", COLORS*3, COLORS*3*EMPHASIS_COMBINATIONS),
+        }
+    }
+}
+
+impl std::error::Error for PaletteError{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)>{
+        match self{
+            PaletteError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for PaletteError{
+    fn from(err: std::io::Error) -> Self{
+        PaletteError::Io(err)
+    }
+}
+
+//turns the PPU output (6 bit index and 3 emphasis bits) into RGB, one entry per index and emphasis combination
+#[derive(Clone)]
+pub struct Palette{
+    colors: Vec<[u8; 3]>,
+}
+
+impl Default for Palette{
+    fn default() -> Self{
+        Palette::withEmphasis(&DEFAULT_PALETTE)
+    }
+}
+
+impl Palette{
+    pub fn load(path: &str) -> Result<Palette, PaletteError>{
+        let mut file = File::open(path)?;
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+        Palette::fromBytes(&contents)
+    }
+    
+    //.pal files: 64 RGB triplets, or 512 with the emphasis combinations in order
+    pub fn fromBytes(contents: &[u8]) -> Result<Palette, PaletteError>{
+        let colors: Vec<[u8; 3]> = contents.chunks_exact(3).map(|rgb| [rgb[0], rgb[1], rgb[2]]).collect();
+        match contents.len(){
+            len if len == COLORS*3 => Ok(Palette::withEmphasis(&colors)),
+            len if len == COLORS*3*EMPHASIS_COMBINATIONS => Ok(Palette{colors}),
+            len => Err(PaletteError::BadSize(len)),
+        }
+    }
+    
+    //files without emphasis entries get them computed: each emphasis bit dims the two other channels
+    fn withEmphasis(base: &[[u8; 3]]) -> Palette{
+        let mut colors = Vec::with_capacity(COLORS*EMPHASIS_COMBINATIONS);
+        for emphasis in 0..EMPHASIS_COMBINATIONS{
+            for (index, rgb) in base.iter().enumerate(){
+                let mut rgb = *rgb;
+                //$xE and $xF are forced black, emphasis doesn't touch them
+                if index & 0x0E != 0x0E{
+                    for bit in 0..3{
+                        if emphasis & (1 << bit) == 0{
+                            continue;
+                        }
+                        for (channel, value) in rgb.iter_mut().enumerate(){
+                            if channel != bit{
+                                *value = (*value as u16 * 3 / 4) as u8;
+                            }
+                        }
+                    }
+                }
+                colors.push(rgb);
+            }
+        }
+        Palette{colors}
+    }
+    
+    pub fn rgb(&self, pixel: u16) -> [u8; 3]{
+        self.colors[pixel as usize % self.colors.len()]
+    }
+    
+    //RGB24, 3 bytes per pixel
+    pub fn convert(&self, framebuffer: &[u16]) -> Vec<u8>{
+        framebuffer.iter().flat_map(|&pixel| self.rgb(pixel)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn fileSizes(){
+        assert!(Palette::fromBytes(&[0; 192]).is_ok());
+        assert!(Palette::fromBytes(&[0; 1536]).is_ok());
+        assert!(matches!(Palette::fromBytes(&[0; 100]), Err(PaletteError::BadSize(100))));
+        assert!(matches!(Palette::load("/nonexistent/nes.pal"), Err(PaletteError::Io(_))));
+    }
+
+    #[test]
+    fn fullPaletteIsUsedAsIs(){
+        let contents: Vec<u8> = (0..512).flat_map(|i: u16| [(i >> 8) as u8, i as u8, 0]).collect();
+        let palette = Palette::fromBytes(&contents).unwrap();
+        assert_eq!(palette.rgb(0x000), [0, 0, 0]);
+        assert_eq!(palette.rgb(0x1FF), [1, 0xFF, 0]);
+    }
+
+    #[test]
+    fn emphasisDimsTheOtherChannels(){
+        let palette = Palette::fromBytes(&[200; 192]).unwrap();
+        assert_eq!(palette.rgb(0x01), [200, 200, 200]);
+        assert_eq!(palette.rgb(0x01 | 0x40), [200, 150, 150]); //red
+        assert_eq!(palette.rgb(0x01 | 0x80), [150, 200, 150]); //green
+        assert_eq!(palette.rgb(0x01 | 0x100), [150, 150, 200]); //blue
+        assert_eq!(palette.rgb(0x01 | 0x1C0), [112, 112, 112]);
+        //emphasis leaves $xE and $xF alone
+        assert_eq!(palette.rgb(0x0E | 0x40), [200, 200, 200]);
+    }
+
+    #[test]
+    fn convert(){
+        let palette = Palette::default();
+        assert_eq!(palette.convert(&[0x00, 0x30, 0x0F]), [84, 84, 84, 236, 238, 236, 0, 0, 0]);
+    }
+}
@@ -1,20 +1,27 @@
 #![allow(non_snake_case)]
+#![allow(clippy::upper_case_acronyms)]
+#![allow(clippy::identity_op)]
 
 mod CPU;
+mod Opcodes;
 mod Bus_NES;
+mod Bus_Flat;
 mod PPU_NES;
+mod APU_NES;
+mod Console_NES;
+mod Palette_NES;
+mod Cartridge_NES;
+mod Header_NES;
+mod Mappers;
 
 use crate::CPU::*;
-use crate::Bus_NES::*;
-use crate::PPU_NES::*;
+use crate::Bus_Flat::*;
+use crate::Console_NES::*;
+use crate::PPU_NES::Renderer;
+use crate::Palette_NES::Palette;
+use crate::Cartridge_NES::*;
 
-use std::fs::File;
-use std::io::Read;
-
-pub trait Mapper{
-    fn read(&self, adr: usize) -> u8;
-    fn write(&mut self, adr: usize,  data: u8);
-}
+use std::process::ExitCode;
 
 /*pub trait BinaryHandler{
     fn bit<T: From<bool>>(&self, bit:usize) -> T;
@@ -41,179 +48,292 @@ impl BinaryHandler for u8{
     }
 }
 
-pub struct Cartridge{
-    prgROM: Vec<u8>,
-    chrROM: Vec<u8>,
-    ram: Vec<u8>,
+fn parseNumber(arg: &str) -> Option<u16>{
+    if let Some(hex) = arg.strip_prefix("0x").or_else(|| arg.strip_prefix('$')){
+        u16::from_str_radix(hex, 16).ok()
+    }
+    else {
+        arg.parse().ok()
+    }
 }
 
-impl Cartridge{
-    fn new(path: &str) -> Cartridge {//Result<Cartridge, std::io::Error>{
-        let mut file = File::open(path).expect("Can't find ROM file !");
-        let mut ram = vec![0u8; 0x10000];
-        file.read_exact(&mut ram).expect("failed to read rom!!");
-        ram[0xFFFC] = 0x00;
-        ram[0xFFFD] = 0x04;
-        return Cartridge{ram: ram, prgROM:vec![0u8], chrROM: vec![0u8]};
-        
-        //let mut contents = Vec::new();
-        //file.read_to_end(&mut contents).expect("Failed to read ROM file");
-        //let bytes = file.bytes();//.take(16).collect();
-        let mut header = vec![0u8;16];
-        file.read_exact(&mut header).unwrap();
-        
-        //let header: Vec<u8> = bytes.take(16).map(|x|x.unwrap()).collect();
-        
-        if !(header[0] == b'N' && header[1] == b'E' && header[2] == b'S' && header[3] == 0x1A){
-            //return Err("dsfsdfs");
-            panic!("I don't handle other header formats than iNes");
-        }
-        
-        //let var:u8 = contents[5].bit(4);
-        
-        let prgSize = (header[4] as usize)*16;//in kb (1024 bytes)
-        let chrSize = (header[5] as usize)*8;
-        println!("prgSize: {prgSize} chrSize: {chrSize}");
-        println!("{} + {} = {}", prgSize*1024, chrSize*1024, (prgSize+chrSize)*1024);
-        
-        let mut PRG_ROM = vec![0u8; prgSize*1024];
-        file.read_exact(&mut PRG_ROM).unwrap();
-        
-        let mut CHR_ROM = vec![0u8; chrSize*1024];
-        file.read_exact(&mut CHR_ROM).unwrap();
-        
-        let flags6 = header[6];
-        let flags7 = header[7];
-        let flags8 = header[8];
-        let flags9 = header[9];
-        let flags10 = header[10];
-        
-        let mut nes2Format = false;
-        if flags7 & 0x0C == 0x08{
-            println!("NES 2.0 format !");
-            panic!("format not handled yet !");
-            nes2Format = true;
-        }
-        else {
-            println!("NES 1.0 format !");
-        }
-        
-        let mapper = (flags6 >> 4) | (flags7 & 0xF0);
-        println!("Mapper: {}", mapper);
-        
-        if flags6.flag(0){
-            println!("Horizontal arrangement (vertically mirrored)");
-        }
-        else{
-            println!("Vertical arrangement (horizontally mirrored)");
-        }
-        
-        if flags6.flag(1){
-            println!("Contains Battery powered RAM");
-        }
-        
-        if flags6.flag(2){
-            println!("512 bytes trainer present !");
+fn usage(){
+    println!("usage: nes [--start ADR] <rom.nes>");
+    println!("       nes --raw [--load ADR] [--start ADR] [--fill BYTE] [--success ADR] [--cycles N] <binary>");
+    println!("options: --bus-conflicts on|off   override the bus conflicts setting of the ROM");
+    println!("         --autosave SECONDS       how often battery backed RAM is written to the .sav file, 0 turns it off (default: 5)");
+    println!("         --undefined halt|nop|trap what to do on undefined and JAM opcodes (default: halt)");
+    println!("         --trace                  print every instruction before it runs");
+    println!("         --frames N               stop after N frames");
+    println!("         --renderer dot|scanline  PPU renderer, scanline is faster but less accurate (default: dot)");
+    println!("         --palette FILE           .pal file with 64 or 512 colors (default: built in NTSC palette)");
+    println!("         --start ADR              with a .nes ROM, jump to ADR after the reset sequence (nestest's automated mode is at $C000)");
+    println!("         --success ADR            raw binaries stop on the first instruction that jumps to itself, it passed if that's at ADR");
+    println!("         --cycles N               raw binaries fail if they haven't stopped after N CPU cycles");
+}
+
+struct Options{
+    path: String,
+    raw: bool,
+    rawOptions: RawBinaryOptions,
+    success: Option<u16>,
+    cycles: Option<u64>,
+    busConflicts: Option<bool>,
+    autosave: Option<std::time::Duration>,
+    frames: Option<u64>,
+    undefinedPolicy: UndefinedOpcodePolicy,
+    trace: bool,
+    renderer: Renderer,
+    palette: Option<String>,
+}
+
+fn parseArgs(args: &[String]) -> Result<Options, String>{
+    let mut options = Options{
+        path: String::new(),
+        raw: false,
+        rawOptions: RawBinaryOptions::default(),
+        success: None,
+        cycles: None,
+        busConflicts: None,
+        //the save is also written on exit, but killing the process (Ctrl-C) skips that
+        autosave: Some(std::time::Duration::from_secs(5)),
+        frames: None,
+        undefinedPolicy: UndefinedOpcodePolicy::Halt,
+        trace: false,
+        renderer: Renderer::Dot,
+        palette: None,
+    };
+    
+    if args.is_empty(){
+        //Klaus Dormann's functional test: loaded at $0000 and started at $0400, the success trap of the default build is at $3469
+        options.path = "games/6502_functional_test.bin".to_owned();
+        options.raw = true;
+        options.rawOptions.startPC = Some(0x0400);
+        options.success = Some(0x3469);
+        return Ok(options);
+    }
+
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next(){
+        match arg.as_str(){
+            "--raw" => options.raw = true,
+            "--trace" => options.trace = true,
+            "--load" | "--start" | "--fill" | "--success" => {
+                let value = args.next().and_then(|value| parseNumber(value)).ok_or(format!("{arg} expects a number"))?;
+                match arg.as_str(){
+                    "--load" => options.rawOptions.loadAddress = value,
+                    "--start" => options.rawOptions.startPC = Some(value),
+                    "--success" => options.success = Some(value),
+                    _ => options.rawOptions.fillByte = u8::try_from(value).map_err(|_| "--fill expects a byte".to_owned())?,
+                }
+            }
+            "--bus-conflicts" => options.busConflicts = match args.next().map(|value| value.as_str()){
+                Some("on") => Some(true),
+                Some("off") => Some(false),
+                _ => return Err("--bus-conflicts expects on or off".to_owned()),
+            },
+            "--autosave" => {
+                let seconds = args.next().and_then(|value| value.parse().ok()).ok_or("--autosave expects a number of seconds".to_owned())?;
+                options.autosave = if seconds == 0 {None} else {Some(std::time::Duration::from_secs(seconds))};
+            }
+            "--frames" => options.frames = Some(args.next().and_then(|value| value.parse().ok()).ok_or("--frames expects a number".to_owned())?),
+            "--cycles" => options.cycles = Some(args.next().and_then(|value| value.parse().ok()).ok_or("--cycles expects a number".to_owned())?),
+            "--undefined" => options.undefinedPolicy = match args.next().map(|value| value.as_str()){
+                Some("halt") => UndefinedOpcodePolicy::Halt,
+                Some("nop") => UndefinedOpcodePolicy::Nop,
+                Some("trap") => UndefinedOpcodePolicy::Trap,
+                _ => return Err("--undefined expects halt, nop or trap".to_owned()),
+            },
+            "--renderer" => options.renderer = match args.next().map(|value| value.as_str()){
+                Some("dot") => Renderer::Dot,
+                Some("scanline") => Renderer::Scanline,
+                _ => return Err("--renderer expects dot or scanline".to_owned()),
+            },
+            "--palette" => options.palette = Some(args.next().ok_or("--palette expects a file".to_owned())?.clone()),
+            _ => path = Some(arg.clone()),
         }
-        
-        if flags6.flag(3){
-            println!("Alternative nametable layout");
+    }
+
+    options.path = path.ok_or("no ROM file given".to_owned())?;
+    //there's no PPU to count frames without a ROM, and no trap to wait for with one
+    if options.raw && options.frames.is_some(){
+        return Err("--frames needs a .nes ROM, use --cycles with --raw".to_owned());
+    }
+    if !options.raw && (options.cycles.is_some() || options.success.is_some()){
+        return Err("--cycles and --success only apply to --raw".to_owned());
+    }
+    Ok(options)
+}
+
+fn loadCartridge(options: &Options) -> Result<Cartridge, String>{
+    let path = options.path.as_str();
+    let cartridge = if options.raw{
+        Cartridge::fromRawBinary(path, &options.rawOptions)
+    }
+    else {
+        Cartridge::new(path)
+    };
+    let mut cartridge = cartridge.map_err(|err| format!("{path}: {err}"))?;
+    
+    if let Some(enabled) = options.busConflicts{
+        cartridge.setBusConflicts(enabled);
+    }
+    cartridge.setAutosaveInterval(options.autosave);
+    Ok(cartridge)
+}
+
+//a trap is reported and stepped over, anything else stops the emulation
+fn handleCpuError<B: CpuBus>(cpu: &mut CPU6502<B>, err: CpuError) -> bool{
+    println!("{err}");
+    if let CpuError::Trap{..} = err{
+        println!("{cpu}");
+        cpu.skipTrappedOpcode();
+        return true;
+    }
+    false
+}
+
+//raw binaries run on the CPU alone, until they trap or run out of cycles
+fn runRaw(mut cpu: CPU6502<FlatBus>, options: &Options) -> ExitCode{
+    cpu.setUndefinedOpcodePolicy(options.undefinedPolicy);
+    
+    let mut cycles = 0;
+    while options.cycles.is_none_or(|limit| cycles < limit){
+        if cpu.inSelfLoop(){
+            let pc = cpu.pc();
+            if options.success.is_none_or(|success| success == pc){
+                println!("trapped at ${pc:04X} after {cycles} cycles");
+                return ExitCode::SUCCESS;
+            }
+            println!("failed: trapped at ${pc:04X} after {cycles} cycles\n{cpu}");
+            return ExitCode::FAILURE;
         }
-        
-        if flags7.flag(0){
-            println!("VS Unisystem");
+        if options.trace && cpu.atInstructionBoundary(){
+            println!("{}", cpu.trace());
         }
-        
-        if flags7.flag(7){
-            println!("Playchoice ");
+        if let Err(err) = cpu.tick(){
+            if !handleCpuError(&mut cpu, err){
+                return ExitCode::FAILURE;
+            }
         }
-        
-        let prgRAM = flags8;
-        println!("PRG RAM: {}",prgRAM);
-        
-        if flags9.flag(0){
-            println!("PAL system");
+        cycles += 1;
+    }
+    println!("failed: no trap after {cycles} cycles\n{cpu}");
+    ExitCode::FAILURE
+}
+
+fn runNes(mut nes: Nes, options: &Options) -> ExitCode{
+    nes.cpuMut().setUndefinedOpcodePolicy(options.undefinedPolicy);
+    nes.setRenderer(options.renderer);
+    if let Some(path) = &options.palette{
+        match Palette::load(path){
+            Ok(palette) => nes.setPalette(palette),
+            Err(err) => {
+                println!("{path}: {err}");
+                return ExitCode::FAILURE;
+            }
         }
-        else {
-            println!("NTSC system");
+    }
+    
+    if let Some(start) = options.rawOptions.startPC{
+        if let Err(err) = nes.stepInstruction(){
+            println!("{err}");
+            return ExitCode::FAILURE;
         }
-        
-        if flags10.flag(4){
-            println!("No prgRAM");
+        nes.cpuMut().setPC(start);
+    }
+    
+    //returning drops the Nes, which writes the save file
+    let mut resetFrame = None;
+    while options.frames.is_none_or(|frames| nes.bus().ppu.frame() < frames){
+        let result = if options.trace {stepFrameTraced(&mut nes)} else {nes.stepFrame()};
+        if let Err(err) = result{
+            if !handleCpuError(nes.cpuMut(), err){
+                return ExitCode::FAILURE;
+            }
         }
         
-        if flags10.flag(5){
-            println!("Board has bus conflicts");
+        let frame = nes.bus().ppu.frame();
+        match testStatus(&mut nes){
+            Some(TestStatus::Done(code, text)) => {
+                println!("{text}");
+                println!("test ROM result: {code}");
+                return if code == 0 {ExitCode::SUCCESS} else {ExitCode::FAILURE};
+            }
+            //held for at least 100ms like a person would
+            Some(TestStatus::ResetRequested) if resetFrame.is_none() => resetFrame = Some(frame + 6),
+            _ => (),
         }
-        
-        if (header[11] != 0 || header[12] != 0 || header[13] != 0 || header[14] != 0 || header[15] != 0) && !nes2Format{
-            panic!("not loading this shit");
+        if resetFrame == Some(frame){
+            resetFrame = None;
+            nes.reset();
         }
-        
-        /*if flags6.bit::<i32>(2) == 1{ //default is bool, this works but is ugly as fuck
-            println!("test");
-        }*/
-        
-        Cartridge { prgROM: PRG_ROM, chrROM: CHR_ROM , ram:vec![0u8]}
-        /*if let Some(byte) = file.bytes().next(){
-            println!("{}",byte?.bit::<bool>(5));
-        }*/
-        /* 
-        let mut contents = Vec::new();
-        file.read_to_end(&mut contents).expect("Failed to read ROM file");
-        //println!("{contents:?}");
-        
-        println!("header type: {}",contents.iter().take(3).map(|a| *a as char).collect::<String>());
-        println!("{}",contents[1]);
-        if contents[4].bit(2){
-            println!("dcsdc");
-        }*/
-        //Ok(Cartridge {  })
     }
-    
-    //cartridge would own a mapper object of a certain empty type: Mapper0 that implements mapper => then the cartridge would be a generic type
-    
-    /*fn read(&self, adr: usize) -> u8{ 
-        self.prgROM[adr - 0x8000]
-    }*/
-    
-    /*fn write(&self, adr: usize, data: u8){
-        
-    }*/
+    ExitCode::SUCCESS
+}
+
+enum TestStatus{
+    Running,
+    ResetRequested,
+    Done(u8, String),
 }
 
-impl Mapper for Cartridge{
-    fn read(&self, adr: usize) -> u8{
-        /*match adr{
-            0x8000..=0xBFFF => self.prgROM[adr - 0x8000],
-            0xC000..=0xFFFF => self.prgROM[adr - 0xC000],
-            _ => {panic!("not handled {adr:#x}");}
-        }*/
-        return self.ram[adr];
+//blargg's test ROMs report through the PRG RAM: $6001-$6003 hold DE B0 61 once $6000 is valid,
+//$6000 is $80 while running, $81 when they want the reset button and the result code when done (0 passed),
+//with the text they print on screen from $6004
+fn testStatus(nes: &mut Nes) -> Option<TestStatus>{
+    let bus = nes.busMut();
+    if [0x6001, 0x6002, 0x6003].map(|adr| bus.peek(adr)) != [0xDE, 0xB0, 0x61]{
+        return None;
+    }
+    match bus.peek(0x6000){
+        0x80 => Some(TestStatus::Running),
+        0x81 => Some(TestStatus::ResetRequested),
+        code => {
+            let text: Vec<u8> = (0x6004..0x8000).map(|adr| bus.peek(adr)).take_while(|&byte| byte != 0).collect();
+            Some(TestStatus::Done(code, String::from_utf8_lossy(&text).into_owned()))
+        }
     }
-    fn write(&mut self, adr: usize,  data: u8){
-        self.ram[adr] = data;
+}
+
+//Nes::stepFrame printing every instruction before it runs
+fn stepFrameTraced(nes: &mut Nes) -> Result<(), CpuError>{
+    nes.clearAudioSamples();
+    let frame = nes.bus().ppu.frame();
+    while nes.bus().ppu.frame() == frame{
+        if nes.cpu().atInstructionBoundary(){
+            //the PPU position and CPU cycle columns of the nestest log
+            let (scanline, dot, cycle) = (nes.bus().ppu.scanline(), nes.bus().ppu.dot(), nes.bus().cycle());
+            println!("{} PPU:{scanline:3},{dot:3} CYC:{cycle}", nes.cpuMut().trace());
+        }
+        nes.stepCycle()?;
     }
+    Ok(())
 }
-fn main() {
+
+fn main() -> ExitCode{
     println!("NES Emulator");
     
-    let mut cartridge = Cartridge::new("games/6502_functional_test.bin"); //nestest.nes");//.unwrap();
-    
-    let mut ppu = PPU::new();
-    let mut bus = Bus::new(&mut cartridge, &mut ppu);
-    
-    let mut cpu = CPU6502::new(&mut bus);
-    
-    let debugMode = true;
-    
-    loop{
-        cpu.tick();
-        
-        if debugMode{
-            cpu.debugMode();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match parseArgs(&args){
+        Ok(options) => options,
+        Err(err) => {
+            println!("{err}");
+            usage();
+            return ExitCode::FAILURE;
         }
-        
-        //return;
+    };
+    let cartridge = match loadCartridge(&options){
+        Ok(cartridge) => cartridge,
+        Err(err) => {
+            println!("Failed to load ROM: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    
+    if cartridge.isRawBinary(){
+        return runRaw(CPU6502::new(FlatBus::new(cartridge)), &options);
     }
+    runNes(Nes::new(cartridge), &options)
 }